@@ -1,7 +1,40 @@
 use enumflags2::{bitflags, BitFlags};
 
+use crate::channels::Channels;
 use crate::client::group::{is_member_in_group, ClientMembershipQuery};
 
+/// Permissions granted everywhere unless an ACL revokes them
+pub const DEFAULT_PERMISSIONS: BitFlags<ACLPermissions> = BitFlags::<ACLPermissions>::from_bits_truncate_c(
+    ACLPermissions::Traverse as u32
+        | ACLPermissions::Enter as u32
+        | ACLPermissions::Speak as u32
+        | ACLPermissions::Whisper as u32
+        | ACLPermissions::TextMessage as u32
+        | ACLPermissions::Listen as u32,
+    BitFlags::CONST_TOKEN,
+);
+
+/// Permissions that Write implies within a channel. Root-only permissions such as
+/// Kick and Ban have to be granted explicitly.
+pub const CHANNEL_PERMISSIONS: BitFlags<ACLPermissions> = BitFlags::<ACLPermissions>::from_bits_truncate_c(
+    ACLPermissions::Write as u32
+        | ACLPermissions::Traverse as u32
+        | ACLPermissions::Enter as u32
+        | ACLPermissions::Speak as u32
+        | ACLPermissions::MuteDeafen as u32
+        | ACLPermissions::Move as u32
+        | ACLPermissions::MakeChannel as u32
+        | ACLPermissions::LinkChannel as u32
+        | ACLPermissions::Whisper as u32
+        | ACLPermissions::TextMessage as u32
+        | ACLPermissions::TempChannel as u32
+        | ACLPermissions::Listen as u32,
+    BitFlags::CONST_TOKEN,
+);
+
+/// The SuperUser account is always granted every permission
+pub const SUPERUSER_ID: u32 = 0;
+
 pub struct ACL {
     user_id: Option<i32>,
     group: Option<String>,
@@ -51,6 +84,23 @@ impl ACL {
         }
     }
 
+    pub fn for_group(
+        group: &str,
+        apply_here: bool,
+        apply_subs: bool,
+        allow: BitFlags<ACLPermissions>,
+        deny: BitFlags<ACLPermissions>,
+    ) -> Self {
+        ACL {
+            user_id: None,
+            group: Some(group.to_string()),
+            apply_here,
+            apply_subs,
+            allow,
+            deny,
+        }
+    }
+
     pub fn is_user_acl(&self) -> bool {
         self.user_id.is_some()
     }
@@ -85,3 +135,127 @@ impl ACL {
         }
     }
 }
+
+/// Computes the permissions a client holds in a channel.
+///
+/// ACLs are applied from the outermost channel that does not inherit its parent's ACLs
+/// down to the target channel. Losing Traverse anywhere on the path revokes everything,
+/// and Write implies every other channel permission.
+pub fn effective_permissions(
+    channels: &Channels,
+    channel_id: u32,
+    user_id: Option<u32>,
+    client: &ClientMembershipQuery,
+) -> BitFlags<ACLPermissions> {
+    if user_id == Some(SUPERUSER_ID) {
+        return BitFlags::all();
+    }
+
+    let mut chain = Vec::new();
+    let mut current = channels.get_channel(channel_id);
+    while let Some(channel) = current {
+        chain.push(channel);
+        if !channel.inherits_acl() {
+            break;
+        }
+        current = channels.get_parent(channel);
+    }
+
+    let mut granted = DEFAULT_PERMISSIONS;
+    for channel in chain.into_iter().rev() {
+        let is_target = channel.get_id() == channel_id;
+
+        for acl in channel.get_acls() {
+            if !((is_target && acl.apply_here) || (!is_target && acl.apply_subs)) {
+                continue;
+            }
+
+            let matched = match user_id {
+                Some(id) if acl.is_user_acl() => acl.match_user(id as i32),
+                _ => acl.match_group(channel.get_id(), Some(channel_id), &[], client),
+            };
+
+            if matched {
+                granted |= acl.allow;
+                granted &= !acl.deny;
+            }
+        }
+
+        if !granted.contains(ACLPermissions::Traverse) && !granted.contains(ACLPermissions::Write) {
+            return BitFlags::empty();
+        }
+    }
+
+    if granted.contains(ACLPermissions::Write) {
+        granted |= CHANNEL_PERMISSIONS;
+    }
+
+    granted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::ROOT_CHANNEL_ID;
+
+    fn permissions(channels: &Channels, user_id: Option<u32>, groups: &[&str]) -> BitFlags<ACLPermissions> {
+        let client = ClientMembershipQuery::new(groups, user_id.is_some(), &[], None, false, None, None, None);
+        effective_permissions(channels, ROOT_CHANNEL_ID, user_id, &client)
+    }
+
+    fn with_root_acls(acls: Vec<ACL>) -> Channels {
+        let mut channels = Channels::new("Root");
+        channels.get_channel_mut(ROOT_CHANNEL_ID).unwrap().set_acls(acls);
+        channels
+    }
+
+    #[test]
+    fn default_channel_acls() {
+        let channels = Channels::new("Root");
+
+        assert_eq!(permissions(&channels, None, &[]), DEFAULT_PERMISSIONS);
+        assert_eq!(permissions(&channels, Some(5), &["admin"]), BitFlags::all());
+        assert_eq!(permissions(&channels, Some(SUPERUSER_ID), &[]), BitFlags::all());
+    }
+
+    #[test]
+    fn write_implies_channel_permissions_only() {
+        let channels = with_root_acls(vec![ACL::for_group(
+            "auth",
+            true,
+            true,
+            ACLPermissions::Write.into(),
+            BitFlags::empty(),
+        )]);
+
+        let granted = permissions(&channels, Some(5), &[]);
+        assert_eq!(granted, CHANNEL_PERMISSIONS);
+        assert!(!granted.intersects(ACLPermissions::Kick | ACLPermissions::Ban | ACLPermissions::Register));
+
+        assert_eq!(permissions(&channels, None, &[]), DEFAULT_PERMISSIONS);
+    }
+
+    #[test]
+    fn losing_traverse_revokes_everything() {
+        let channels = with_root_acls(vec![
+            ACL::for_group("all", true, true, BitFlags::empty(), ACLPermissions::Traverse.into()),
+            ACL::for_group("admin", true, true, ACLPermissions::Write.into(), BitFlags::empty()),
+        ]);
+
+        assert!(permissions(&channels, Some(5), &[]).is_empty());
+        assert_eq!(permissions(&channels, Some(5), &["admin"]), CHANNEL_PERMISSIONS);
+    }
+
+    #[test]
+    fn acls_only_apply_where_flagged() {
+        let channels = with_root_acls(vec![ACL::for_group(
+            "all",
+            false,
+            true,
+            BitFlags::empty(),
+            ACLPermissions::Speak.into(),
+        )]);
+
+        assert_eq!(permissions(&channels, None, &[]), DEFAULT_PERMISSIONS);
+    }
+}
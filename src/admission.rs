@@ -0,0 +1,163 @@
+use std::net::IpAddr;
+
+use crate::{
+    client::group::IPMaskType,
+    config::{AdmissionAction, AdmissionConfig},
+    geoip,
+};
+
+struct Rule {
+    action: AdmissionAction,
+    invert: bool,
+    mask: IPMaskType<'static>,
+}
+
+pub enum AdmissionDecision {
+    Allow,
+    /// Denied by the rule at `rule_index`, or by the default action when `None`
+    Deny { rule_index: Option<usize> },
+}
+
+/// Ordered allow/deny rules checked right after proxy protocol resolution
+pub struct AdmissionPolicy {
+    dry_run: bool,
+    default_action: AdmissionAction,
+    rules: Vec<Rule>,
+}
+
+impl Rule {
+    fn parse(action: AdmissionAction, mask: &str) -> Result<Self, String> {
+        let (invert, body) = match mask.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, mask),
+        };
+
+        let parsed = body
+            .strip_prefix('%')
+            .and_then(IPMaskType::parse)
+            .ok_or_else(|| format!("Invalid admission rule mask: {}", mask))?;
+
+        Ok(Rule {
+            action,
+            invert,
+            mask: parsed.into_owned(),
+        })
+    }
+
+    fn matches(&self, ip_address: IpAddr, geoip: &geoip::Config) -> bool {
+        let matched = self
+            .mask
+            .matches(Some(ip_address), geoip.asn, geoip.country_code.as_deref());
+        matched != self.invert
+    }
+}
+
+impl AdmissionPolicy {
+    pub fn new(config: &AdmissionConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| Rule::parse(rule.action, &rule.mask))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(AdmissionPolicy {
+            dry_run: config.dry_run,
+            default_action: config.default_action,
+            rules,
+        })
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn evaluate(&self, ip_address: IpAddr, geoip: &geoip::Config) -> AdmissionDecision {
+        let (action, rule_index) = self
+            .rules
+            .iter()
+            .enumerate()
+            .find(|(_, rule)| rule.matches(ip_address, geoip))
+            .map(|(index, rule)| (rule.action, Some(index)))
+            .unwrap_or((self.default_action, None));
+
+        match action {
+            AdmissionAction::Allow => AdmissionDecision::Allow,
+            AdmissionAction::Deny => AdmissionDecision::Deny { rule_index },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AdmissionRule;
+
+    fn policy(default_action: AdmissionAction, rules: &[(AdmissionAction, &str)]) -> AdmissionPolicy {
+        AdmissionPolicy::new(&AdmissionConfig {
+            dry_run: false,
+            default_action,
+            rules: rules
+                .iter()
+                .map(|(action, mask)| AdmissionRule {
+                    action: *action,
+                    mask: mask.to_string(),
+                })
+                .collect(),
+        })
+        .unwrap()
+    }
+
+    fn geo(country_code: &str, asn: u32) -> geoip::Config {
+        geoip::Config {
+            country_code: Some(country_code.to_string()),
+            asn: Some(asn),
+            ..Default::default()
+        }
+    }
+
+    fn is_allowed(decision: AdmissionDecision) -> bool {
+        matches!(decision, AdmissionDecision::Allow)
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let policy = policy(
+            AdmissionAction::Deny,
+            &[
+                (AdmissionAction::Allow, "%!10.0.0.0/8"),
+                (AdmissionAction::Deny, "%@14061"),
+                (AdmissionAction::Allow, "%#DE"),
+            ],
+        );
+
+        // Allowed CIDR wins over the hoster ASN
+        assert!(is_allowed(policy.evaluate("10.1.2.3".parse().unwrap(), &geo("DE", 14061))));
+        assert!(!is_allowed(policy.evaluate("192.0.2.1".parse().unwrap(), &geo("DE", 14061))));
+        assert!(is_allowed(policy.evaluate("192.0.2.1".parse().unwrap(), &geo("de", 3320))));
+        // Falls through to the default action
+        assert!(!is_allowed(policy.evaluate("192.0.2.1".parse().unwrap(), &geo("FR", 3215))));
+    }
+
+    #[test]
+    fn inverted_rule() {
+        let policy = policy(AdmissionAction::Allow, &[(AdmissionAction::Deny, "!%#DE")]);
+
+        assert!(is_allowed(policy.evaluate("192.0.2.1".parse().unwrap(), &geo("DE", 3320))));
+        assert!(!is_allowed(policy.evaluate("192.0.2.1".parse().unwrap(), &geo("US", 7922))));
+        // Unknown location counts as not matching the country
+        assert!(!is_allowed(policy.evaluate("192.0.2.1".parse().unwrap(), &geoip::Config::default())));
+    }
+
+    #[test]
+    fn rejects_invalid_masks() {
+        let config = AdmissionConfig {
+            dry_run: false,
+            default_action: AdmissionAction::Allow,
+            rules: vec![AdmissionRule {
+                action: AdmissionAction::Deny,
+                mask: "%@hoster".to_string(),
+            }],
+        };
+        assert!(AdmissionPolicy::new(&config).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use crate::{
+    client::client::Client,
+    config::AfkConfig,
+    messages::Message,
+    mumble_proto::{UserRemove, UserState},
+    server::Server,
+};
+
+async fn is_exempt(config: &AfkConfig, client: &Client) -> bool {
+    if config.exempt_registered && client.is_registered().await {
+        return true;
+    }
+
+    for group in &config.exempt_groups {
+        if client.has_group(group).await {
+            return true;
+        }
+    }
+    false
+}
+
+/// Whether a message was sent by the user rather than by their client on its own.
+/// Plugin context and identity updates are sent automatically and don't count.
+pub fn is_user_activity(message: &Message) -> bool {
+    match message {
+        Message::UDPTunnel(_) | Message::TextMessage(_) | Message::ChannelState(_) => true,
+        Message::UserState(state) => {
+            state.channel_id.is_some()
+                || state.mute.is_some()
+                || state.deaf.is_some()
+                || state.suppress.is_some()
+                || state.self_mute.is_some()
+                || state.self_deaf.is_some()
+                || state.texture.is_some()
+                || state.comment.is_some()
+                || state.priority_speaker.is_some()
+                || state.recording.is_some()
+                || !state.listening_channel_add.is_empty()
+                || !state.listening_channel_remove.is_empty()
+        }
+        _ => false,
+    }
+}
+
+/// Moves idle users to the AFK channel and disconnects those idle for too long
+pub async fn check_idle_clients(server: &Server) {
+    let config = server.get_afk_config();
+    let idle_limit = Duration::from_secs(config.idle_secs);
+    let disconnect_limit = config
+        .disconnect_after_secs
+        .map(|secs| idle_limit + Duration::from_secs(secs));
+    let afk_channel_id = match config.channel_id {
+        Some(channel_id) if server.get_channels().await.get_channel(channel_id).is_some() => Some(channel_id),
+        _ => None,
+    };
+
+    for client in server.get_clients().get_clients().await {
+        if !client.is_authenticated().await || is_exempt(config, &client).await {
+            continue;
+        }
+
+        let idle = client.get_idle_time().await;
+        if disconnect_limit.is_some_and(|limit| idle >= limit) {
+            tracing::info!(session_id = client.get_session_id(), idle_secs = idle.as_secs(), "Disconnecting idle user");
+            client
+                .disconnect_with(UserRemove {
+                    session: client.get_session_id(),
+                    actor: None,
+                    reason: Some("Disconnected for being idle".to_string()),
+                    ban: None,
+                })
+                .await;
+            continue;
+        }
+
+        let Some(afk_channel_id) = afk_channel_id else {
+            continue;
+        };
+        if idle < idle_limit || client.get_current_channel_id().await == afk_channel_id {
+            continue;
+        }
+
+        if server.is_channel_full(afk_channel_id).await {
+            tracing::debug!(session_id = client.get_session_id(), "AFK channel is full, not moving idle user");
+            continue;
+        }
+
+        let mut state = UserState::default();
+        if config.deafen {
+            let mut states = client.get_session_states_mut().await;
+            states.set_self_deaf(true);
+            state.self_mute = Some(states.is_self_mute());
+            state.self_deaf = Some(states.is_self_deaf());
+        }
+
+        // The user counts as the actor, the move happens on their behalf
+        tracing::info!(session_id = client.get_session_id(), idle_secs = idle.as_secs(), "Moving idle user to the AFK channel");
+        if let Err(e) = server.move_user(&client, afk_channel_id, client.get_session_id(), state).await {
+            tracing::debug!(session_id = client.get_session_id(), "Failed to move idle user: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plugin_updates_are_not_activity() {
+        let plugin = UserState {
+            session: Some(1),
+            plugin_context: Some(b"game".to_vec()),
+            plugin_identity: Some("player".to_string()),
+            ..Default::default()
+        };
+        assert!(!is_user_activity(&Message::UserState(plugin)));
+        assert!(!is_user_activity(&Message::Ping(Default::default())));
+
+        let mute = UserState { self_mute: Some(true), ..Default::default() };
+        assert!(is_user_activity(&Message::UserState(mute)));
+        assert!(is_user_activity(&Message::TextMessage(Default::default())));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{
+    config::{Config, TextMessageConfig},
+    html_sanitizer::HtmlSanitizer,
+    mumble_proto::{ServerConfig, SuggestConfig},
+    protocol_version::ProtocolVersion,
+};
+
+/// Server settings announced to clients, rebuilt from the configuration on reload.
+/// The limits enforced to match them live here too so both change together.
+pub struct Announcements {
+    max_bandwidth: u32,
+    welcome_text: Option<String>,
+    server_config: ServerConfig,
+    suggest_config: Option<SuggestConfig>,
+    text_messages: TextMessageConfig,
+    html_sanitizer: HtmlSanitizer,
+}
+
+impl Announcements {
+    pub fn from_config(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let info = &config.server_info;
+        let welcome_text = match &info.welcome_text_path {
+            Some(path) => Some(std::fs::read_to_string(path)?),
+            None => info.welcome_text.clone(),
+        };
+
+        let server_config = ServerConfig {
+            max_bandwidth: None,
+            welcome_text: None,
+            allow_html: Some(config.html.text_message.allow_html),
+            message_length: Some(config.text_messages.max_length as u32),
+            image_message_length: Some(config.text_messages.max_image_length as u32),
+            max_users: info.max_users,
+            recording_allowed: Some(info.recording_allowed),
+        };
+
+        let suggestions = &config.suggestions;
+        let version = suggestions
+            .version
+            .as_deref()
+            .map(str::parse::<ProtocolVersion>)
+            .transpose()?;
+        let suggest_config = match (version, suggestions.positional, suggestions.push_to_talk) {
+            (None, None, None) => None,
+            (version, positional, push_to_talk) => Some(SuggestConfig {
+                version_v1: version.map(Into::into),
+                version_v2: version.map(Into::into),
+                positional,
+                push_to_talk,
+            }),
+        };
+
+        Ok(Announcements {
+            max_bandwidth: info.max_bandwidth,
+            welcome_text,
+            server_config,
+            suggest_config,
+            text_messages: config.text_messages.clone(),
+            html_sanitizer: HtmlSanitizer::new(&config.html),
+        })
+    }
+
+    pub fn get_max_bandwidth(&self) -> u32 {
+        self.max_bandwidth
+    }
+
+    pub fn get_welcome_text(&self) -> Option<&str> {
+        self.welcome_text.as_deref()
+    }
+
+    pub fn get_max_users(&self) -> Option<u32> {
+        self.server_config.max_users
+    }
+
+    pub fn is_recording_allowed(&self) -> bool {
+        self.server_config.recording_allowed.unwrap_or(true)
+    }
+
+    pub fn get_text_message_config(&self) -> &TextMessageConfig {
+        &self.text_messages
+    }
+
+    pub fn get_html_sanitizer(&self) -> &HtmlSanitizer {
+        &self.html_sanitizer
+    }
+
+    /// Sent after ServerSync, which already carries the bandwidth and welcome text
+    pub fn get_sync_config(&self) -> ServerConfig {
+        self.server_config.clone()
+    }
+
+    /// Sent to connected users on reload
+    pub fn get_full_config(&self) -> ServerConfig {
+        ServerConfig {
+            max_bandwidth: Some(self.max_bandwidth),
+            welcome_text: self.welcome_text.clone(),
+            ..self.server_config.clone()
+        }
+    }
+
+    pub fn get_suggest_config(&self) -> Option<SuggestConfig> {
+        self.suggest_config.clone()
+    }
+}
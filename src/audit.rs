@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::{io::AsyncWriteExt, sync::Mutex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Kick,
+    Ban,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub time: DateTime<Utc>,
+    pub action: AuditAction,
+    pub actor_session: u32,
+    pub actor_name: Option<String>,
+    pub actor_user_id: Option<u32>,
+    pub target_session: u32,
+    pub target_name: Option<String>,
+    pub target_user_id: Option<u32>,
+    pub target_address: std::net::IpAddr,
+    /// Hex encoded SHA-1 of the target's certificate
+    pub target_certificate_hash: Option<String>,
+    pub reason: String,
+}
+
+/// Moderation actions, appended to a JSON lines file and mirrored to the log
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    file: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(path: Option<&str>) -> Self {
+        AuditLog {
+            path: path.map(PathBuf::from),
+            file: Mutex::new(()),
+        }
+    }
+
+    pub async fn record(&self, entry: &AuditEntry) -> Result<(), Box<dyn std::error::Error>> {
+        tracing::info!(
+            action = ?entry.action,
+            actor_session = entry.actor_session,
+            actor_name = entry.actor_name,
+            target_session = entry.target_session,
+            target_name = entry.target_name,
+            target_address = %entry.target_address,
+            reason = entry.reason,
+            "Moderation action"
+        );
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        // Serialize appends so that concurrent entries never interleave
+        let _guard = self.file.lock().await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(&line).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use cidr::{AnyIpCidr, IpCidr};
+use tokio::sync::Mutex;
+
+use crate::{bans::Ban, config::AutobanConfig, rate_limit::address_prefix};
+
+#[derive(Default)]
+struct Counters {
+    connections: VecDeque<Instant>,
+    failed_authentications: VecDeque<Instant>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum AutobanEvent {
+    Connection,
+    FailedAuthentication,
+}
+
+/// A ban issued because a prefix crossed one of the thresholds
+#[derive(Debug)]
+pub struct Autoban {
+    pub prefix: IpCidr,
+    pub connections: usize,
+    pub failed_authentications: usize,
+    pub ban: Ban,
+}
+
+/// Counts connection attempts and failed authentications per source prefix
+/// over a sliding window and issues timed bans once a threshold is crossed.
+pub struct AutobanTracker {
+    config: AutobanConfig,
+    allowlist: Vec<AnyIpCidr>,
+    counters: Mutex<HashMap<IpCidr, Counters>>,
+}
+
+fn expire(timestamps: &mut VecDeque<Instant>, cutoff: Instant) {
+    while timestamps.front().is_some_and(|t| *t < cutoff) {
+        timestamps.pop_front();
+    }
+}
+
+impl Counters {
+    fn expire(&mut self, cutoff: Instant) {
+        expire(&mut self.connections, cutoff);
+        expire(&mut self.failed_authentications, cutoff);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.connections.is_empty() && self.failed_authentications.is_empty()
+    }
+}
+
+impl AutobanTracker {
+    pub fn new(
+        config: &AutobanConfig,
+        allowed_proxies: &[AnyIpCidr],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut allowlist = config
+            .allowlist
+            .iter()
+            .map(|cidr| AnyIpCidr::from_str(cidr))
+            .collect::<Result<Vec<_>, _>>()?;
+        allowlist.extend_from_slice(allowed_proxies);
+
+        Ok(AutobanTracker {
+            config: config.clone(),
+            allowlist,
+            counters: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    fn window(&self) -> Duration {
+        Duration::from_secs(self.config.window_secs)
+    }
+
+    pub async fn record(&self, address: IpAddr, event: AutobanEvent) -> Option<Autoban> {
+        self.record_at(address, event, Instant::now()).await
+    }
+
+    async fn record_at(&self, address: IpAddr, event: AutobanEvent, now: Instant) -> Option<Autoban> {
+        if !self.config.enabled {
+            return None;
+        }
+
+        let address = address.to_canonical();
+        if self.allowlist.iter().any(|cidr| cidr.contains(&address)) {
+            return None;
+        }
+
+        let prefix = address_prefix(
+            address,
+            self.config.ipv4_prefix_length,
+            self.config.ipv6_prefix_length,
+        );
+
+        let mut counters_guard = self.counters.lock().await;
+        let counters = counters_guard.entry(prefix).or_default();
+        counters.expire(now.checked_sub(self.window()).unwrap_or(now));
+
+        match event {
+            AutobanEvent::Connection => counters.connections.push_back(now),
+            AutobanEvent::FailedAuthentication => counters.failed_authentications.push_back(now),
+        }
+
+        let connections = counters.connections.len();
+        let failed_authentications = counters.failed_authentications.len();
+
+        let exceeded = |count: usize, threshold: Option<u32>| {
+            threshold.is_some_and(|threshold| count > threshold as usize)
+        };
+
+        if !exceeded(connections, self.config.max_connections)
+            && !exceeded(failed_authentications, self.config.max_failed_authentications)
+        {
+            return None;
+        }
+
+        counters_guard.remove(&prefix);
+
+        Some(Autoban {
+            prefix,
+            connections,
+            failed_authentications,
+            ban: Ban {
+                address: prefix.first_address(),
+                mask: prefix.network_length(),
+                name: String::new(),
+                certificate_hash: None,
+                reason: format!(
+                    "Autoban: {} connections and {} failed authentications within {} seconds",
+                    connections, failed_authentications, self.config.window_secs
+                ),
+                start: Utc::now(),
+                duration: self.config.ban_duration_secs,
+            },
+        })
+    }
+
+    /// Forgets prefixes without events inside the window
+    pub async fn sweep(&self) {
+        let now = Instant::now();
+        let cutoff = now.checked_sub(self.window()).unwrap_or(now);
+        self.counters.lock().await.retain(|_, counters| {
+            counters.expire(cutoff);
+            !counters.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(allowed_proxies: &[AnyIpCidr]) -> AutobanTracker {
+        let config = AutobanConfig {
+            enabled: true,
+            max_connections: Some(3),
+            max_failed_authentications: Some(1),
+            ..Default::default()
+        };
+        AutobanTracker::new(&config, allowed_proxies).unwrap()
+    }
+
+    #[tokio::test]
+    async fn bans_prefix_after_threshold() {
+        let tracker = tracker(&[]);
+        let now = Instant::now();
+        let address: IpAddr = "2001:db8::1".parse().unwrap();
+
+        for i in 0..3 {
+            let at = now + Duration::from_secs(i);
+            assert!(tracker.record_at(address, AutobanEvent::Connection, at).await.is_none());
+        }
+
+        // Another address in the same /64 is counted together
+        let other: IpAddr = "2001:db8::2".parse().unwrap();
+        let autoban = tracker
+            .record_at(other, AutobanEvent::Connection, now + Duration::from_secs(3))
+            .await
+            .unwrap();
+        assert_eq!(autoban.connections, 4);
+        assert_eq!(autoban.ban.mask, 64);
+        assert!(autoban.ban.matches_address(address));
+    }
+
+    #[tokio::test]
+    async fn window_slides() {
+        let tracker = tracker(&[]);
+        let now = Instant::now();
+        let address: IpAddr = "192.0.2.1".parse().unwrap();
+
+        let event = AutobanEvent::FailedAuthentication;
+        assert!(tracker.record_at(address, event, now).await.is_none());
+        // The first failure has left the 60 second window
+        assert!(tracker.record_at(address, event, now + Duration::from_secs(61)).await.is_none());
+        assert!(tracker.record_at(address, event, now + Duration::from_secs(62)).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn honors_allowlist() {
+        let tracker = tracker(&[AnyIpCidr::from_str("192.0.2.0/24").unwrap()]);
+        let now = Instant::now();
+        let address: IpAddr = "192.0.2.1".parse().unwrap();
+
+        for i in 0..10 {
+            let at = now + Duration::from_secs(i);
+            assert!(tracker.record_at(address, AutobanEvent::FailedAuthentication, at).await.is_none());
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandwidthVerdict {
+    Accept,
+    Drop,
+    /// Dropped, and the sender has now been dropped often enough to be told about it
+    DropAndWarn,
+}
+
+/// Voice bitrate of one sender over a sliding window
+#[derive(Debug, Default)]
+pub struct BandwidthMeter {
+    packets: VecDeque<(Instant, usize)>,
+    bytes: usize,
+    dropped: u32,
+    warned: bool,
+}
+
+impl BandwidthMeter {
+    fn expire(&mut self, now: Instant, window: Duration) {
+        while let Some(&(time, size)) = self.packets.front() {
+            if now.duration_since(time) < window {
+                break;
+            }
+            self.bytes -= size;
+            self.packets.pop_front();
+        }
+    }
+
+    /// Average rate over the window, in bytes per second
+    pub fn get_rate(&mut self, now: Instant, window: Duration) -> u32 {
+        self.expire(now, window);
+        (self.bytes as f64 / window.as_secs_f64()) as u32
+    }
+
+    /// Records a packet unless it would push the rate over `max_bytes_per_sec`.
+    /// Only accepted packets count towards the rate.
+    pub fn check(
+        &mut self,
+        now: Instant,
+        window: Duration,
+        size: usize,
+        max_bytes_per_sec: u32,
+        warn_after_drops: u32,
+    ) -> BandwidthVerdict {
+        self.expire(now, window);
+        let allowance = (max_bytes_per_sec as f64 * window.as_secs_f64()) as usize;
+        if self.bytes + size <= allowance {
+            self.bytes += size;
+            self.packets.push_back((now, size));
+            return BandwidthVerdict::Accept;
+        }
+
+        self.dropped = self.dropped.saturating_add(1);
+        if !self.warned && self.dropped >= warn_after_drops {
+            self.warned = true;
+            return BandwidthVerdict::DropAndWarn;
+        }
+        BandwidthVerdict::Drop
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_over_cap_and_warns_once() {
+        let start = Instant::now();
+        let window = Duration::from_secs(1);
+        let mut meter = BandwidthMeter::default();
+
+        assert_eq!(meter.check(start, window, 600, 1000, 2), BandwidthVerdict::Accept);
+        assert_eq!(meter.check(start, window, 600, 1000, 2), BandwidthVerdict::Drop);
+        assert_eq!(meter.check(start, window, 600, 1000, 2), BandwidthVerdict::DropAndWarn);
+        assert_eq!(meter.check(start, window, 600, 1000, 2), BandwidthVerdict::Drop);
+        assert_eq!(meter.get_rate(start, window), 600);
+
+        let later = start + Duration::from_secs(1);
+        assert_eq!(meter.get_rate(later, window), 0);
+        assert_eq!(meter.check(later, window, 600, 1000, 2), BandwidthVerdict::Accept);
+    }
+}
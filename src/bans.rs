@@ -0,0 +1,300 @@
+use std::{
+    net::{IpAddr, Ipv6Addr},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use cidr::IpInet;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::mumble_proto::ban_list::BanEntry;
+
+const BAN_START_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ban {
+    pub address: IpAddr,
+    /// Prefix length in the address family of `address`. 0 disables address matching.
+    pub mask: u8,
+    pub name: String,
+    /// Hex encoded SHA-1 of the client certificate
+    pub certificate_hash: Option<String>,
+    pub reason: String,
+    pub start: DateTime<Utc>,
+    /// Seconds. 0 means the ban never expires.
+    pub duration: u32,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BanEntryError {
+    #[error("address must be 4 or 16 bytes long")]
+    InvalidAddress,
+    #[error("mask exceeds the address length")]
+    InvalidMask,
+}
+
+pub struct Bans {
+    path: Option<PathBuf>,
+    bans: RwLock<Vec<Ban>>,
+}
+
+impl Ban {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.duration != 0 && self.start + chrono::Duration::seconds(self.duration as i64) <= now
+    }
+
+    pub fn matches_address(&self, address: IpAddr) -> bool {
+        if self.mask == 0 {
+            return false;
+        }
+
+        // Wide IPv6 bans can cover mapped IPv4 space, so compare in the ban's family
+        let address = match (self.address, address.to_canonical()) {
+            (IpAddr::V6(_), IpAddr::V4(v4)) => IpAddr::V6(v4.to_ipv6_mapped()),
+            (_, address) => address,
+        };
+
+        match IpInet::new(self.address, self.mask) {
+            Ok(inet) => inet.network().contains(&address),
+            Err(_) => false,
+        }
+    }
+
+    pub fn matches_certificate_hash(&self, hash: &[u8]) -> bool {
+        match &self.certificate_hash {
+            Some(expected) => expected.eq_ignore_ascii_case(&hex::encode(hash)),
+            None => false,
+        }
+    }
+
+    /// Converts a wire entry. Addresses arrive as IPv6, with IPv4 mapped into `::ffff:0:0/96`.
+    pub fn from_entry(entry: &BanEntry, now: DateTime<Utc>) -> Result<Self, BanEntryError> {
+        let (address, mask) = match entry.address.len() {
+            4 => {
+                let octets: [u8; 4] = entry.address[..].try_into().unwrap();
+                (IpAddr::from(octets), entry.mask)
+            }
+            16 => {
+                let octets: [u8; 16] = entry.address[..].try_into().unwrap();
+                let address = Ipv6Addr::from(octets);
+                match address.to_ipv4_mapped() {
+                    Some(v4) if entry.mask >= 96 => (IpAddr::V4(v4), entry.mask - 96),
+                    _ => (IpAddr::V6(address), entry.mask),
+                }
+            }
+            _ => return Err(BanEntryError::InvalidAddress),
+        };
+
+        let max_mask = if address.is_ipv4() { 32 } else { 128 };
+        if mask > max_mask {
+            return Err(BanEntryError::InvalidMask);
+        }
+
+        let start = entry
+            .start
+            .as_deref()
+            .and_then(|s| NaiveDateTime::parse_from_str(s, BAN_START_FORMAT).ok())
+            .map(|s| s.and_utc())
+            .unwrap_or(now);
+
+        Ok(Ban {
+            address,
+            mask: mask as u8,
+            name: entry.name.clone().unwrap_or_default(),
+            certificate_hash: entry.hash.clone().filter(|h| !h.is_empty()),
+            reason: entry.reason.clone().unwrap_or_default(),
+            start,
+            duration: entry.duration.unwrap_or(0),
+        })
+    }
+
+    pub fn to_entry(&self) -> BanEntry {
+        let (address, mask) = match self.address {
+            IpAddr::V4(v4) => (v4.to_ipv6_mapped(), self.mask as u32 + 96),
+            IpAddr::V6(v6) => (v6, self.mask as u32),
+        };
+
+        BanEntry {
+            address: address.octets().to_vec(),
+            mask,
+            name: Some(self.name.clone()),
+            hash: self.certificate_hash.clone(),
+            reason: Some(self.reason.clone()),
+            start: Some(self.start.format(BAN_START_FORMAT).to_string()),
+            duration: Some(self.duration),
+        }
+    }
+}
+
+impl Bans {
+    pub fn load(path: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        let Some(path) = path else {
+            return Ok(Bans {
+                path: None,
+                bans: RwLock::new(Vec::new()),
+            });
+        };
+
+        let path = PathBuf::from(path);
+        let bans: Vec<Ban> = match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Bans {
+            path: Some(path),
+            bans: RwLock::new(bans),
+        })
+    }
+
+    async fn save(&self, bans: &[Ban]) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = &self.path {
+            tokio::fs::write(path, serde_json::to_vec_pretty(bans)?).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_bans(&self) -> Vec<Ban> {
+        self.bans.read().await.clone()
+    }
+
+    pub async fn find_by_address(&self, address: IpAddr) -> Option<Ban> {
+        let now = Utc::now();
+        self.bans
+            .read()
+            .await
+            .iter()
+            .find(|ban| !ban.is_expired(now) && ban.matches_address(address))
+            .cloned()
+    }
+
+    pub async fn find_by_certificate_hash(&self, hash: &[u8]) -> Option<Ban> {
+        let now = Utc::now();
+        self.bans
+            .read()
+            .await
+            .iter()
+            .find(|ban| !ban.is_expired(now) && ban.matches_certificate_hash(hash))
+            .cloned()
+    }
+
+    pub async fn replace(&self, bans: Vec<Ban>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut guard = self.bans.write().await;
+        *guard = bans;
+        self.save(&guard).await
+    }
+
+    /// Adds a ban unless an active ban already covers the same address and certificate.
+    /// Returns whether the list changed.
+    pub async fn add(&self, ban: Ban) -> Result<bool, Box<dyn std::error::Error>> {
+        let now = Utc::now();
+        let mut guard = self.bans.write().await;
+        let duplicate = guard.iter().any(|existing| {
+            !existing.is_expired(now)
+                && existing.address == ban.address
+                && existing.mask == ban.mask
+                && existing.certificate_hash == ban.certificate_hash
+        });
+        if duplicate {
+            return Ok(false);
+        }
+
+        guard.push(ban);
+        self.save(&guard).await?;
+        Ok(true)
+    }
+
+    /// Drops expired bans and returns how many were removed
+    pub async fn prune_expired(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let now = Utc::now();
+        let mut guard = self.bans.write().await;
+        let before = guard.len();
+        guard.retain(|ban| !ban.is_expired(now));
+        let pruned = before - guard.len();
+
+        if pruned > 0 {
+            self.save(&guard).await?;
+        }
+
+        Ok(pruned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(address: Vec<u8>, mask: u32) -> BanEntry {
+        BanEntry {
+            address,
+            mask,
+            name: None,
+            hash: None,
+            reason: None,
+            start: Some("2024-01-01T00:00:00".to_string()),
+            duration: Some(60),
+        }
+    }
+
+    #[test]
+    fn mapped_ipv4_entry_roundtrip() {
+        let mapped = "::ffff:192.0.2.0".parse::<Ipv6Addr>().unwrap().octets().to_vec();
+        let ban = Ban::from_entry(&entry(mapped, 120), Utc::now()).unwrap();
+
+        assert_eq!(ban.address, "192.0.2.0".parse::<IpAddr>().unwrap());
+        assert_eq!(ban.mask, 24);
+        assert!(ban.matches_address("192.0.2.77".parse().unwrap()));
+        assert!(ban.matches_address("::ffff:192.0.2.77".parse().unwrap()));
+        assert!(!ban.matches_address("192.0.3.1".parse().unwrap()));
+
+        let back = ban.to_entry();
+        assert_eq!(back.mask, 120);
+        assert_eq!(back.start.as_deref(), Some("2024-01-01T00:00:00"));
+    }
+
+    #[test]
+    fn wide_ipv6_ban_matches_mapped_ipv4() {
+        let mapped = "::ffff:192.0.2.0".parse::<Ipv6Addr>().unwrap().octets().to_vec();
+        let ban = Ban::from_entry(&entry(mapped, 80), Utc::now()).unwrap();
+
+        assert!(ban.address.is_ipv6());
+        assert!(ban.matches_address("192.0.2.77".parse().unwrap()));
+        assert!(ban.matches_address("::ffff:198.51.100.1".parse().unwrap()));
+        assert!(!ban.matches_address("2001:db8::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn zero_mask_never_matches_address() {
+        let ban = Ban::from_entry(&entry(vec![0; 16], 0), Utc::now()).unwrap();
+        assert!(!ban.matches_address("2001:db8::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn expiry() {
+        let ban = Ban::from_entry(&entry(vec![10, 0, 0, 1], 32), Utc::now()).unwrap();
+        assert!(ban.is_expired(ban.start + chrono::Duration::seconds(60)));
+        assert!(!ban.is_expired(ban.start + chrono::Duration::seconds(59)));
+
+        let permanent = Ban { duration: 0, ..ban };
+        assert!(!permanent.is_expired(Utc::now()));
+    }
+
+    #[tokio::test]
+    async fn add_skips_active_duplicates() {
+        let bans = Bans::load(None).unwrap();
+        let ban = Ban::from_entry(&entry(vec![10, 0, 0, 1], 32), Utc::now()).unwrap();
+        let ban = Ban { start: Utc::now(), ..ban };
+
+        assert!(bans.add(ban.clone()).await.unwrap());
+        assert!(!bans.add(ban.clone()).await.unwrap());
+        assert!(bans.add(Ban { mask: 24, ..ban }).await.unwrap());
+        assert_eq!(bans.get_bans().await.len(), 2);
+    }
+
+    #[test]
+    fn rejects_oversized_mask() {
+        assert!(Ban::from_entry(&entry(vec![10, 0, 0, 1], 33), Utc::now()).is_err());
+    }
+}
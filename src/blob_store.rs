@@ -0,0 +1,175 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::RwLock;
+
+/// Length of a SHA-1 digest, the key Mumble clients use for blobs
+pub const BLOB_HASH_LENGTH: usize = 20;
+
+pub fn blob_hash(data: &[u8]) -> Vec<u8> {
+    aws_lc_rs::digest::digest(&aws_lc_rs::digest::SHA1_FOR_LEGACY_USE_ONLY, data)
+        .as_ref()
+        .to_vec()
+}
+
+/// Content-addressed storage for comments, textures and channel descriptions.
+///
+/// Blobs are kept in memory and, when a directory is configured, also written
+/// to `<directory>/<hex hash>` so they survive restarts.
+pub struct BlobStore {
+    directory: Option<PathBuf>,
+    blobs: RwLock<HashMap<Vec<u8>, StoredBlob>>,
+}
+
+struct StoredBlob {
+    /// `None` until a blob found on disk at startup is first read
+    data: Option<Arc<[u8]>>,
+    stored_at: Instant,
+}
+
+impl BlobStore {
+    /// Opens the store and indexes blobs already in `directory` so that ones left
+    /// unreferenced from before a restart are collected too.
+    pub fn open(directory: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        let directory = directory.map(PathBuf::from);
+        let mut blobs = HashMap::new();
+        if let Some(directory) = &directory {
+            std::fs::create_dir_all(directory)?;
+            for file in std::fs::read_dir(directory)? {
+                let hash = file?.file_name().to_str().and_then(|name| hex::decode(name).ok());
+                if let Some(hash) = hash.filter(|hash| hash.len() == BLOB_HASH_LENGTH) {
+                    blobs.insert(
+                        hash,
+                        StoredBlob {
+                            data: None,
+                            stored_at: Instant::now(),
+                        },
+                    );
+                }
+            }
+        }
+
+        Ok(BlobStore {
+            directory,
+            blobs: RwLock::new(blobs),
+        })
+    }
+
+    fn blob_path(&self, hash: &[u8]) -> Option<PathBuf> {
+        self.directory.as_ref().map(|directory| directory.join(hex::encode(hash)))
+    }
+
+    /// Stores `data` and returns its hash. Storing the same content twice is a no-op.
+    pub async fn put(&self, data: Vec<u8>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let hash = blob_hash(&data);
+        let mut guard = self.blobs.write().await;
+        if let Some(blob) = guard.get_mut(&hash) {
+            blob.stored_at = Instant::now();
+            return Ok(hash);
+        }
+
+        if let Some(path) = self.blob_path(&hash) {
+            tokio::fs::write(path, &data).await?;
+        }
+        guard.insert(
+            hash.clone(),
+            StoredBlob {
+                data: Some(data.into()),
+                stored_at: Instant::now(),
+            },
+        );
+        Ok(hash)
+    }
+
+    pub async fn get(&self, hash: &[u8]) -> Option<Arc<[u8]>> {
+        if hash.len() != BLOB_HASH_LENGTH {
+            return None;
+        }
+
+        if let Some(data) = self.blobs.read().await.get(hash).and_then(|blob| blob.data.as_ref()) {
+            return Some(Arc::clone(data));
+        }
+
+        let data = tokio::fs::read(self.blob_path(hash)?).await.ok()?;
+        if blob_hash(&data) != hash {
+            tracing::warn!(hash = hex::encode(hash), "Blob on disk does not match its hash");
+            return None;
+        }
+
+        let blob: Arc<[u8]> = data.into();
+        self.blobs
+            .write()
+            .await
+            .entry(hash.to_vec())
+            .or_insert_with(|| StoredBlob {
+                data: None,
+                stored_at: Instant::now(),
+            })
+            .data = Some(Arc::clone(&blob));
+        Some(blob)
+    }
+
+    /// Drops unreferenced blobs stored more than `grace` ago and returns how many were removed.
+    /// The grace period covers blobs stored but not yet attached to a user or channel.
+    pub async fn retain(&self, live: &HashSet<Vec<u8>>, grace: Duration) -> usize {
+        let mut guard = self.blobs.write().await;
+        let dead = guard
+            .iter()
+            .filter(|(hash, blob)| !live.contains(*hash) && blob.stored_at.elapsed() >= grace)
+            .map(|(hash, _)| hash.clone())
+            .collect::<Vec<_>>();
+
+        for hash in &dead {
+            guard.remove(hash);
+            if let Some(path) = self.blob_path(hash) {
+                if let Err(e) = tokio::fs::remove_file(&path).await {
+                    tracing::warn!(path = %path.display(), "Failed to remove blob: {}", e);
+                }
+            }
+        }
+
+        dead.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stores_by_content() {
+        let store = BlobStore::open(None).unwrap();
+        let hash = store.put(b"hello".to_vec()).await.unwrap();
+
+        assert_eq!(hex::encode(&hash), "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d");
+        assert_eq!(store.put(b"hello".to_vec()).await.unwrap(), hash);
+        assert_eq!(store.get(&hash).await.as_deref(), Some(&b"hello"[..]));
+
+        assert_eq!(store.retain(&HashSet::new(), Duration::from_secs(60)).await, 0);
+        assert_eq!(store.retain(&HashSet::from([hash.clone()]), Duration::ZERO).await, 0);
+        assert_eq!(store.retain(&HashSet::new(), Duration::ZERO).await, 1);
+        assert!(store.get(&hash).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn collects_blobs_left_from_before_a_restart() {
+        let directory = std::env::temp_dir().join(format!("blob-store-{}", std::process::id()));
+        let path = directory.to_str().unwrap();
+        let (kept, dropped) = {
+            let store = BlobStore::open(Some(path)).unwrap();
+            (store.put(b"kept".to_vec()).await.unwrap(), store.put(b"dropped".to_vec()).await.unwrap())
+        };
+
+        let store = BlobStore::open(Some(path)).unwrap();
+        assert_eq!(store.retain(&HashSet::from([kept.clone()]), Duration::ZERO).await, 1);
+        assert_eq!(store.get(&kept).await.as_deref(), Some(&b"kept"[..]));
+        assert!(store.get(&dropped).await.is_none());
+        assert!(!directory.join(hex::encode(&dropped)).exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,5 +1,11 @@
 use std::collections::HashMap;
 
+use enumflags2::BitFlags;
+
+use crate::{acl::ACL, mumble_proto::ChannelState};
+
+pub const ROOT_CHANNEL_ID: u32 = 0;
+
 pub struct Channel {
     id: u32,
     name: String,
@@ -8,7 +14,9 @@ pub struct Channel {
     parent_id: Option<u32>,
     inherit_acl: bool,
     link: Option<u32>,
-    description_blob: Option<String>,
+    /// Blob store key of the description
+    description_hash: Option<Vec<u8>>,
+    acls: Vec<ACL>,
 }
 
 pub struct Channels {
@@ -24,7 +32,7 @@ impl Channel {
         parent_id: Option<u32>,
         inherit_acl: bool,
         link: Option<u32>,
-        description_blob: Option<String>,
+        description_hash: Option<Vec<u8>>,
     ) -> Self {
         Channel {
             id,
@@ -34,14 +42,64 @@ impl Channel {
             parent_id,
             inherit_acl,
             link,
-            description_blob,
+            description_hash,
+            acls: Vec::new(),
         }
     }
 
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// 0 means no limit
+    pub fn get_max_users(&self) -> u32 {
+        self.max_users
+    }
+
+    pub fn get_parent_id(&self) -> Option<u32> {
+        self.parent_id
+    }
+
+    pub fn inherits_acl(&self) -> bool {
+        self.inherit_acl
+    }
+
+    pub fn get_acls(&self) -> &[ACL] {
+        &self.acls
+    }
+
+    pub fn set_acls(&mut self, acls: Vec<ACL>) {
+        self.acls = acls;
+    }
+
     pub fn has_description(&self) -> bool {
-        match &self.description_blob {
-            Some(desc) => !desc.is_empty(),
-            None => false,
+        self.description_hash.is_some()
+    }
+
+    pub fn get_description_hash(&self) -> Option<&[u8]> {
+        self.description_hash.as_deref()
+    }
+
+    pub fn set_description_hash(&mut self, description_hash: Option<Vec<u8>>) {
+        self.description_hash = description_hash;
+    }
+
+    /// Full state as sent during the initial sync. Only the description hash is included.
+    pub fn to_channel_state(&self) -> ChannelState {
+        ChannelState {
+            channel_id: Some(self.id),
+            parent: self.parent_id,
+            name: Some(self.name.clone()),
+            links: self.link.into_iter().collect(),
+            description_hash: self.description_hash.clone(),
+            temporary: Some(self.is_temporary()),
+            position: Some(self.position),
+            max_users: Some(self.max_users),
+            ..Default::default()
         }
     }
 
@@ -56,10 +114,29 @@ impl Channel {
 }
 
 impl Channels {
+    /// Creates a channel tree holding only the root channel with the default ACLs:
+    /// members of the `admin` group may do anything, everyone else gets the defaults.
+    pub fn new(root_name: &str) -> Self {
+        let mut root = Channel::new(ROOT_CHANNEL_ID, root_name.to_string(), 0, 0, None, true, None, None);
+        root.set_acls(vec![ACL::for_group("admin", true, true, BitFlags::all(), BitFlags::empty())]);
+
+        let mut channel_list = HashMap::new();
+        channel_list.insert(ROOT_CHANNEL_ID, root);
+        Channels { channel_list }
+    }
+
     pub fn get_channel(&self, channel_id: u32) -> Option<&Channel> {
         self.channel_list.get(&channel_id)
     }
 
+    pub fn get_channel_mut(&mut self, channel_id: u32) -> Option<&mut Channel> {
+        self.channel_list.get_mut(&channel_id)
+    }
+
+    pub fn get_channels(&self) -> impl Iterator<Item = &Channel> {
+        self.channel_list.values()
+    }
+
     pub fn get_parent(&self, channel: &Channel) -> Option<&Channel> {
         match channel.parent_id {
             Some(parent_id) => self.channel_list.get(&parent_id),
@@ -72,4 +149,32 @@ impl Channels {
             .filter(|c| c.parent_id == Some(channel.id))
             .collect()
     }
+
+    /// Removes a channel together with its descendants and returns their IDs.
+    /// The root channel cannot be removed.
+    pub fn remove_tree(&mut self, channel_id: u32) -> Vec<u32> {
+        if channel_id == ROOT_CHANNEL_ID {
+            return Vec::new();
+        }
+
+        let ids = self.get_tree_ids(channel_id);
+        for id in &ids {
+            self.channel_list.remove(id);
+        }
+        ids
+    }
+
+    /// IDs of a channel and all of its descendants, parents before children
+    pub fn get_tree_ids(&self, channel_id: u32) -> Vec<u32> {
+        let mut ids = Vec::new();
+        let mut queue = std::collections::VecDeque::from([channel_id]);
+        while let Some(id) = queue.pop_front() {
+            let Some(channel) = self.channel_list.get(&id) else {
+                continue;
+            };
+            ids.push(id);
+            queue.extend(self.get_children(channel).iter().map(|c| c.id));
+        }
+        ids
+    }
 }
@@ -0,0 +1,147 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::config::ChatHistoryConfig;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub sender_name: String,
+    /// Sanitized message body as it was delivered
+    pub message: String,
+    pub time: DateTime<Utc>,
+}
+
+/// Recent channel messages, bounded per channel by count and age
+pub struct ChatHistory {
+    path: Option<PathBuf>,
+    max_messages: usize,
+    max_age: chrono::Duration,
+    channels: RwLock<HashMap<u32, VecDeque<HistoryEntry>>>,
+}
+
+impl ChatHistory {
+    /// Returns `None` when history is disabled
+    pub fn load(config: &ChatHistoryConfig) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+
+        let path = config.path.as_deref().map(PathBuf::from);
+        let channels = match &path {
+            Some(path) => match std::fs::read(path) {
+                Ok(content) => serde_json::from_slice(&content)?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+                Err(e) => return Err(e.into()),
+            },
+            None => HashMap::new(),
+        };
+
+        Ok(Some(ChatHistory {
+            path,
+            max_messages: config.max_messages,
+            max_age: chrono::Duration::seconds(config.max_age_secs as i64),
+            channels: RwLock::new(channels),
+        }))
+    }
+
+    async fn save(&self, channels: &HashMap<u32, VecDeque<HistoryEntry>>) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = &self.path {
+            tokio::fs::write(path, serde_json::to_vec(channels)?).await?;
+        }
+        Ok(())
+    }
+
+    fn is_expired(&self, entry: &HistoryEntry, now: DateTime<Utc>) -> bool {
+        entry.time + self.max_age <= now
+    }
+
+    pub async fn record(&self, channel_ids: &[u32], entry: HistoryEntry) -> Result<(), Box<dyn std::error::Error>> {
+        if channel_ids.is_empty() || self.max_messages == 0 {
+            return Ok(());
+        }
+
+        let mut guard = self.channels.write().await;
+        for channel_id in channel_ids {
+            let entries = guard.entry(*channel_id).or_default();
+            entries.push_back(entry.clone());
+            while entries.len() > self.max_messages
+                || entries.front().is_some_and(|e| self.is_expired(e, entry.time))
+            {
+                entries.pop_front();
+            }
+        }
+        self.save(&guard).await
+    }
+
+    /// Unexpired messages of a channel, oldest first
+    pub async fn recent(&self, channel_id: u32) -> Vec<HistoryEntry> {
+        let now = Utc::now();
+        match self.channels.read().await.get(&channel_id) {
+            Some(entries) => entries.iter().filter(|e| !self.is_expired(e, now)).cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub async fn purge_channels(&self, channel_ids: &[u32]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut guard = self.channels.write().await;
+        let before = guard.len();
+        guard.retain(|id, _| !channel_ids.contains(id));
+        if guard.len() != before {
+            self.save(&guard).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(max_messages: usize) -> ChatHistory {
+        let config = ChatHistoryConfig {
+            enabled: true,
+            max_messages,
+            ..Default::default()
+        };
+        ChatHistory::load(&config).unwrap().unwrap()
+    }
+
+    fn entry(message: &str, time: DateTime<Utc>) -> HistoryEntry {
+        HistoryEntry {
+            sender_name: "alice".to_string(),
+            message: message.to_string(),
+            time,
+        }
+    }
+
+    #[tokio::test]
+    async fn bounded_by_count_and_age() {
+        let history = history(2);
+        let now = Utc::now();
+
+        history.record(&[1], entry("old", now - chrono::Duration::days(2))).await.unwrap();
+        history.record(&[1], entry("a", now)).await.unwrap();
+        history.record(&[1, 2], entry("b", now)).await.unwrap();
+        history.record(&[1], entry("c", now)).await.unwrap();
+
+        let messages = history.recent(1).await.into_iter().map(|e| e.message).collect::<Vec<_>>();
+        assert_eq!(messages, ["b", "c"]);
+        assert_eq!(history.recent(2).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn purge_removes_channels() {
+        let history = history(10);
+        history.record(&[1, 2], entry("a", Utc::now())).await.unwrap();
+        history.purge_channels(&[1]).await.unwrap();
+
+        assert!(history.recent(1).await.is_empty());
+        assert_eq!(history.recent(2).await.len(), 1);
+    }
+}
@@ -1,36 +1,47 @@
 use std::{
     collections::HashSet,
     net::{IpAddr, SocketAddr},
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 use chrono::{DateTime, Utc};
-use tokio::{net::TcpStream, sync::{MappedMutexGuard, Mutex, MutexGuard, RwLock}};
+use tokio::{io::{AsyncWriteExt, ReadHalf, WriteHalf}, net::TcpStream, sync::{MappedMutexGuard, Mutex, MutexGuard, Notify, RwLock, RwLockReadGuard, RwLockWriteGuard}};
 use tokio_rustls::server::TlsStream;
 
-use crate::{client::{
-    client_global_state::ClientGlobalState, client_local_state::ClientLocalState, client_session_identifier::ClientSessionIdentifier, client_stats::ClientStats, options::ClientOptions, states::ConnectionState, udp_state::UdpState, user_info::{UserInfo, UserInfoExtended}
-}, messages::{Message, ReadMessageExt, WriteMessageExt}};
+use crate::{acl::ACLPermissions, bandwidth::BandwidthVerdict, config::{MessageSizeLimitsConfig, VoiceBandwidthConfig}, geoip, client::{
+    client_global_state::ClientGlobalState, client_local_state::ClientLocalState, client_session_identifier::ClientSessionIdentifier, client_stats::ClientStats, options::ClientOptions, session_states::SessionStates, states::ConnectionState, udp_state::UdpState, user_info::{UserInfo, UserInfoExtended}, user_version::UserVersion
+}, messages::{Message, ReadMessageExt, WriteMessageExt}, mumble_proto::{permission_denied::DenyType, PermissionDenied, Ping, TextMessage, UserRemove, UserState, Version}, voice_crypto::CryptoCounters};
 
 pub struct Client {
     session_id: ClientSessionIdentifier,
 
     real_ip_address: IpAddr,
+    geoip: geoip::Config,
     tcp_address: SocketAddr,
     udp_address: Option<SocketAddr>,
     local_address: SocketAddr,
 
-    connection: Mutex<TlsStream<TcpStream>>,
+    // Split so that other sessions can write while this one waits for input
+    reader: Mutex<ReadHalf<TlsStream<TcpStream>>>,
+    writer: Mutex<WriteHalf<TlsStream<TcpStream>>>,
+    connection_state: RwLock<ConnectionState>,
+    disconnect_signal: Notify,
+    /// Sent to everyone on teardown instead of a plain leave notice
+    removal: Mutex<Option<UserRemove>>,
+    torn_down: AtomicBool,
 
     // Statistics
     login_time: DateTime<Utc>,
-    last_active: Mutex<DateTime<Utc>>,
     last_ping: Mutex<DateTime<Utc>>,
-    udp_state: Option<Mutex<UdpState>>,
+    udp_state: Mutex<UdpState>,
     stats: RwLock<ClientStats>,
 
     // Might be a registered user, might not
     // Basic user info are synchronized.
     certificate_hash: Option<Vec<u8>>,
+    /// Peer certificates in DER format, leaf first
+    certificate_chain: Vec<Vec<u8>>,
+    verified_certificate_chain: bool,
     user_info: Mutex<Option<UserInfo>>,
     user_info_extended: Mutex<Option<UserInfoExtended>>,
 
@@ -38,17 +49,27 @@ pub struct Client {
 
     local_state: RwLock<Option<ClientLocalState>>,
     global_state: RwLock<ClientGlobalState>,
+    session_states: RwLock<SessionStates>,
 }
 
 impl Client {
     pub fn new_local(
         session_id: ClientSessionIdentifier,
         real_ip_address: IpAddr,
+        geoip: geoip::Config,
         tcp_address: SocketAddr,
         udp_address: Option<SocketAddr>,
         local_address: SocketAddr,
         connection: TlsStream<TcpStream>,
+        verified_certificate_chain: bool,
     ) -> Box<Self> {
+        let certificate_chain: Vec<Vec<u8>> = {
+            let (_, tls_connection) = connection.get_ref();
+            tls_connection
+                .peer_certificates()
+                .map(|certs| certs.iter().map(|cert| cert.as_ref().to_vec()).collect())
+                .unwrap_or_default()
+        };
         let certificate_hash = {
             let (_, tls_connection) = connection.get_ref();
             match tls_connection.peer_certificates() {
@@ -68,25 +89,34 @@ impl Client {
         };
 
         let now = Utc::now();
+        let (reader, writer) = tokio::io::split(connection);
 
         Box::new(Client {
             session_id,
             real_ip_address,
+            geoip,
             tcp_address,
             udp_address,
             local_address,
-            connection: Mutex::new(connection),
+            reader: Mutex::new(reader),
+            writer: Mutex::new(writer),
+            connection_state: RwLock::new(ConnectionState::default()),
+            disconnect_signal: Notify::new(),
+            removal: Mutex::new(None),
+            torn_down: AtomicBool::new(false),
             login_time: now,
-            last_active: Mutex::new(now),
             last_ping: Mutex::new(now),
-            udp_state: None,
+            udp_state: Mutex::new(UdpState::new()),
             stats: RwLock::new(ClientStats::default()),
             certificate_hash,
+            certificate_chain,
+            verified_certificate_chain,
             user_info: Mutex::new(None),
             user_info_extended: Mutex::new(None),
             options: RwLock::new(ClientOptions::default()),
             local_state: RwLock::new(Some(ClientLocalState::new())),
             global_state: RwLock::new(ClientGlobalState::new()),
+            session_states: RwLock::new(SessionStates::new()),
         })
     }
 
@@ -99,6 +129,21 @@ impl Client {
         self.certificate_hash.is_some()
     }
 
+    pub async fn get_connection_state(&self) -> ConnectionState {
+        *self.connection_state.read().await
+    }
+
+    pub async fn set_connection_state(&self, state: ConnectionState) {
+        *self.connection_state.write().await = state;
+    }
+
+    pub async fn is_authenticated(&self) -> bool {
+        matches!(
+            self.get_connection_state().await,
+            ConnectionState::Authenticated | ConnectionState::Ready
+        )
+    }
+
     pub async fn get_groups_clone(&self) -> Option<HashSet<String>> {
         match &*self.user_info.lock().await {
             Some(info) => Some(info.get_groups().clone()),
@@ -117,6 +162,10 @@ impl Client {
         self.certificate_hash.as_deref()
     }
 
+    pub fn get_certificate_chain(&self) -> &[Vec<u8>] {
+        &self.certificate_chain
+    }
+
     pub fn get_session_id(&self) -> u32 {
         self.session_id.into()
     }
@@ -143,6 +192,41 @@ impl Client {
         }
     }
 
+    pub async fn set_tokens(&self, tokens: HashSet<String>) {
+        if let Some(info) = &mut *self.user_info.lock().await {
+            info.set_tokens(tokens);
+        }
+    }
+
+    pub async fn get_session_states(&self) -> RwLockReadGuard<'_, SessionStates> {
+        self.session_states.read().await
+    }
+
+    pub async fn get_session_states_mut(&self) -> RwLockWriteGuard<'_, SessionStates> {
+        self.session_states.write().await
+    }
+
+    pub async fn get_listening_channel_ids(&self) -> Vec<u32> {
+        self.global_state
+            .read().await
+            .get_listening_channel_id()
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    pub async fn listen_channel(&self, channel_id: u32) {
+        self.global_state
+            .write().await
+            .listen_channel(channel_id);
+    }
+
+    pub async fn unlisten_channel(&self, channel_id: u32) {
+        self.global_state
+            .write().await
+            .unlisten_channel(channel_id);
+    }
+
     pub async fn get_current_channel_id(&self) -> u32 {
         self.global_state
             .read().await
@@ -161,6 +245,91 @@ impl Client {
             .get_user_id()
     }
 
+    pub async fn set_user_id(&self, user_id: Option<u32>) {
+        self.global_state
+            .write().await
+            .set_user_id(user_id);
+    }
+
+    pub async fn get_comment_hash(&self) -> Option<Vec<u8>> {
+        self.global_state
+            .read().await
+            .get_comment_hash()
+            .cloned()
+    }
+
+    pub async fn set_comment_hash(&self, comment_hash: Option<Vec<u8>>) {
+        self.global_state
+            .write().await
+            .set_comment_hash(comment_hash);
+    }
+
+    pub async fn get_texture_hash(&self) -> Option<Vec<u8>> {
+        self.global_state
+            .read().await
+            .get_texture_hash()
+            .cloned()
+    }
+
+    pub async fn set_texture_hash(&self, texture_hash: Option<Vec<u8>>) {
+        self.global_state
+            .write().await
+            .set_texture_hash(texture_hash);
+    }
+
+    /// Full state as sent during the initial sync. Comments and textures are sent as hashes only.
+    pub async fn to_user_state(&self) -> UserState {
+        let name = self.get_username().await;
+        let global_state = self.global_state.read().await;
+        let session_states = self.session_states.read().await;
+        UserState {
+            session: Some(self.get_session_id()),
+            name,
+            user_id: global_state.get_user_id(),
+            channel_id: Some(global_state.get_current_channel_id()),
+            mute: Some(session_states.is_mute()),
+            deaf: Some(session_states.is_deaf()),
+            suppress: Some(session_states.is_suppress()),
+            self_mute: Some(session_states.is_self_mute()),
+            self_deaf: Some(session_states.is_self_deaf()),
+            hash: self.certificate_hash.as_ref().map(hex::encode),
+            comment_hash: global_state.get_comment_hash().cloned(),
+            texture_hash: global_state.get_texture_hash().cloned(),
+            priority_speaker: Some(session_states.is_priority_speaker()),
+            recording: Some(session_states.is_recording()),
+            listening_channel_add: global_state.get_listening_channel_id().iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+
+    pub async fn set_user_version(&self, user_version: UserVersion) {
+        self.global_state
+            .write().await
+            .set_user_version(user_version);
+    }
+
+    pub async fn get_version_message(&self) -> Option<Version> {
+        self.global_state
+            .read().await
+            .get_user_version()
+            .map(UserVersion::to_version)
+    }
+
+    pub async fn set_user_info(&self, user_info: UserInfo) {
+        *self.user_info.lock().await = Some(user_info);
+    }
+
+    pub async fn set_user_info_extended(&self, user_info_extended: UserInfoExtended) {
+        *self.user_info_extended.lock().await = Some(user_info_extended);
+    }
+
+    pub async fn get_username(&self) -> Option<String> {
+        self.user_info_extended
+            .lock().await
+            .as_ref()
+            .map(|ext| ext.get_username().to_string())
+    }
+
     // pub fn get_display_name(&self) -> Option<String> {
     //     match &*self.user_info.lock() {
     //         Some(info) => Some(info.get_display_name().clone()),
@@ -171,6 +340,14 @@ impl Client {
     //     }
     // }
 
+    pub fn get_real_ip_address(&self) -> IpAddr {
+        self.real_ip_address
+    }
+
+    pub fn get_geoip(&self) -> &geoip::Config {
+        &self.geoip
+    }
+
     pub fn get_tcp_address(&self) -> SocketAddr {
         self.tcp_address
     }
@@ -179,24 +356,175 @@ impl Client {
         self.udp_address
     }
 
-    // FIXME: not sure if it is verified or just exists
-    pub async fn is_verified(&self) -> bool {
-        let guard = self.connection.lock().await;
-        let (_, conn) = guard.get_ref();
-        conn.peer_certificates().map_or(false, |certs| !certs.is_empty())
+    /// Whether the certificate chain validated against the configured client CA
+    pub fn is_verified(&self) -> bool {
+        self.verified_certificate_chain
     }
 
+    /// Asks the session loop to stop reading and tear the session down
     pub fn disconnect(&self) {
+        self.disconnect_signal.notify_one();
+    }
+
+    /// Disconnects with a kick or ban notice that teardown broadcasts
+    pub async fn disconnect_with(&self, removal: UserRemove) {
+        *self.removal.lock().await = Some(removal);
+        self.disconnect();
+    }
+
+    pub async fn take_removal(&self) -> Option<UserRemove> {
+        self.removal.lock().await.take()
+    }
+
+    /// Returns `true` for the first caller only
+    pub fn begin_teardown(&self) -> bool {
+        !self.torn_down.swap(true, Ordering::AcqRel)
+    }
+
+    /// Closes the TLS session
+    pub async fn close(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.writer.lock().await.shutdown().await?;
+        Ok(())
+    }
+
+    pub async fn record_ping(&self, ping: &Ping) {
+        self.stats.write().await.record_ping(ping);
+        *self.last_ping.lock().await = Utc::now();
+    }
+
+    pub async fn record_tcp_packet(&self, length: usize) {
+        self.stats.write().await.record_tcp_packet(length);
+    }
+
+    /// Records user activity: voice, messages and state changes
+    pub async fn mark_active(&self) {
+        self.global_state.write().await.mark_active();
+    }
+
+    pub async fn get_idle_time(&self) -> std::time::Duration {
+        self.global_state
+            .read().await
+            .get_last_active_timestamp()
+            .map_or(std::time::Duration::ZERO, |t| t.elapsed())
+    }
+
+    pub fn get_online_time(&self) -> chrono::Duration {
+        Utc::now() - self.login_time
+    }
+
+    pub async fn get_last_ping(&self) -> DateTime<Utc> {
+        *self.last_ping.lock().await
+    }
+
+    pub async fn get_stats(&self) -> ClientStats {
+        *self.stats.read().await
+    }
+
+    /// Voice packets received from the client. Zero until UDP has been set up.
+    pub async fn get_local_crypto_counters(&self) -> CryptoCounters {
+        self.udp_state.lock().await.get_local_counters()
+    }
+
+    /// CELT versions and Opus support announced by the client
+    pub async fn get_codecs(&self) -> (Vec<i32>, bool) {
+        let udp_state = self.udp_state.lock().await;
+        (udp_state.get_celt_versions().to_vec(), udp_state.is_opus())
+    }
+
+    pub async fn set_codecs(&self, celt_versions: Vec<i32>, opus: bool) {
+        self.udp_state.lock().await.set_codecs(celt_versions, opus);
+    }
+
+    /// Counts an incoming voice packet against the bandwidth cap
+    pub async fn check_voice_bandwidth(
+        &self,
+        config: &VoiceBandwidthConfig,
+        size: usize,
+        max_bytes_per_sec: u32,
+    ) -> BandwidthVerdict {
+        self.udp_state.lock().await.get_bandwidth_mut().check(
+            std::time::Instant::now(),
+            std::time::Duration::from_millis(config.window_ms),
+            size,
+            max_bytes_per_sec,
+            config.warn_after_drops,
+        )
+    }
+
+    /// Incoming voice rate in bytes per second
+    pub async fn get_voice_bandwidth(&self, config: &VoiceBandwidthConfig) -> u32 {
+        self.udp_state
+            .lock().await
+            .get_bandwidth_mut()
+            .get_rate(std::time::Instant::now(), std::time::Duration::from_millis(config.window_ms))
+    }
+
+    pub async fn clear_voice_targets(&self) {
+        self.udp_state.lock().await.clear_voice_targets();
+    }
+
+    /// Resolves once `disconnect` has been called
+    pub async fn disconnected(&self) {
+        self.disconnect_signal.notified().await
+    }
+
+    pub async fn send_permission_denied(
+        &self,
+        channel_id: u32,
+        permission: ACLPermissions,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut message = PermissionDenied {
+            permission: Some(permission as u32),
+            channel_id: Some(channel_id),
+            session: Some(self.get_session_id()),
+            ..Default::default()
+        };
+        message.set_type(DenyType::Permission);
+        self.write_proto_message(&Message::PermissionDenied(message)).await
+    }
+
+    pub async fn send_denied(&self, deny_type: DenyType) -> Result<(), Box<dyn std::error::Error>> {
+        let mut message = PermissionDenied {
+            session: Some(self.get_session_id()),
+            ..Default::default()
+        };
+        message.set_type(deny_type);
+        self.write_proto_message(&Message::PermissionDenied(message)).await
+    }
+
+    pub async fn send_text_denied(&self, reason: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut message = PermissionDenied {
+            session: Some(self.get_session_id()),
+            reason: Some(reason.to_string()),
+            ..Default::default()
+        };
+        message.set_type(DenyType::Text);
+        self.write_proto_message(&Message::PermissionDenied(message)).await
+    }
 
+    /// Sends a private text message that appears to come from the server
+    pub async fn send_server_message(&self, message: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_proto_message(&Message::TextMessage(TextMessage {
+            actor: None,
+            session: vec![self.get_session_id()],
+            channel_id: Vec::new(),
+            tree_id: Vec::new(),
+            message: message.to_string(),
+        }))
+        .await
     }
 
-    pub async fn read_proto_message(&self) -> Result<Message, Box<dyn std::error::Error>> {
-        let mut guard = self.connection.lock().await;
-        guard.read_proto_message().await
+    pub async fn read_proto_message(
+        &self,
+        limits: &MessageSizeLimitsConfig,
+    ) -> Result<Message, Box<dyn std::error::Error>> {
+        let authenticated = self.is_authenticated().await;
+        let mut guard = self.reader.lock().await;
+        guard.read_proto_message(limits, authenticated).await
     }
 
     pub async fn write_proto_message(&self, message: &Message) -> Result<(), Box<dyn std::error::Error>> {
-        let mut guard = self.connection.lock().await;
+        let mut guard = self.writer.lock().await;
         guard.write_proto_message(message).await
     }
 }
@@ -9,6 +9,10 @@ pub struct ClientGlobalState {
     current_channel_id: u32,
     last_active_timestamp: Option<std::time::Instant>,
     listening_channel_id: HashSet<u32>,
+
+    // Blob store keys
+    comment_hash: Option<Vec<u8>>,
+    texture_hash: Option<Vec<u8>>,
 }
 
 impl ClientGlobalState {
@@ -18,8 +22,11 @@ impl ClientGlobalState {
             user_version: None,
 
             current_channel_id: 0,
-            last_active_timestamp: None,
+            last_active_timestamp: Some(std::time::Instant::now()),
             listening_channel_id: HashSet::new(),
+
+            comment_hash: None,
+            texture_hash: None,
         }
     }
 
@@ -31,6 +38,14 @@ impl ClientGlobalState {
         self.user_id = user_id;
     }
 
+    pub fn get_user_version(&self) -> Option<&UserVersion> {
+        self.user_version.as_ref()
+    }
+
+    pub fn set_user_version(&mut self, user_version: UserVersion) {
+        self.user_version = Some(user_version);
+    }
+
     pub fn set_current_channel_id(&mut self, channel_id: u32) {
         self.current_channel_id = channel_id;
     }
@@ -39,6 +54,30 @@ impl ClientGlobalState {
         self.current_channel_id
     }
 
+    pub fn get_comment_hash(&self) -> Option<&Vec<u8>> {
+        self.comment_hash.as_ref()
+    }
+
+    pub fn set_comment_hash(&mut self, comment_hash: Option<Vec<u8>>) {
+        self.comment_hash = comment_hash;
+    }
+
+    pub fn get_texture_hash(&self) -> Option<&Vec<u8>> {
+        self.texture_hash.as_ref()
+    }
+
+    pub fn set_texture_hash(&mut self, texture_hash: Option<Vec<u8>>) {
+        self.texture_hash = texture_hash;
+    }
+
+    pub fn get_last_active_timestamp(&self) -> Option<std::time::Instant> {
+        self.last_active_timestamp
+    }
+
+    pub fn mark_active(&mut self) {
+        self.last_active_timestamp = Some(std::time::Instant::now());
+    }
+
     pub fn get_listening_channel_id(&self) -> &HashSet<u32> {
         &self.listening_channel_id
     }
@@ -1,3 +1,4 @@
+use crate::{mumble_proto::Ping, voice_crypto::CryptoCounters};
 
 #[derive(Debug, Clone, Copy)]
 pub struct ClientStats {
@@ -11,6 +12,8 @@ pub struct ClientStats {
     tcp_packets: u32,
     tcp_total_packets: u64,
     tcp_volume: u64,
+    /// Packets the client received from the server, as reported by the client
+    remote_counters: CryptoCounters,
 }
 
 impl Default for ClientStats {
@@ -26,6 +29,102 @@ impl Default for ClientStats {
             tcp_packets: 0,
             tcp_total_packets: 0,
             tcp_volume: 0,
+            remote_counters: CryptoCounters::default(),
         }
     }
 }
+
+impl ClientStats {
+    /// Takes over the statistics a client reports in its pings
+    pub fn record_ping(&mut self, ping: &Ping) {
+        if let Some(value) = ping.udp_ping_avg {
+            self.udp_ping_avg = value;
+        }
+        if let Some(value) = ping.udp_ping_var {
+            self.udp_ping_var = value;
+        }
+        if let Some(value) = ping.udp_packets {
+            self.udp_packets = value;
+        }
+        if let Some(value) = ping.tcp_ping_avg {
+            self.tcp_ping_avg = value;
+        }
+        if let Some(value) = ping.tcp_ping_var {
+            self.tcp_ping_var = value;
+        }
+        if let Some(value) = ping.tcp_packets {
+            self.tcp_packets = value;
+        }
+
+        self.remote_counters = CryptoCounters {
+            good: ping.good.unwrap_or(self.remote_counters.good),
+            late: ping.late.unwrap_or(self.remote_counters.late),
+            lost: ping.lost.unwrap_or(self.remote_counters.lost),
+            resync: ping.resync.unwrap_or(self.remote_counters.resync),
+        };
+    }
+
+    /// Counts a message received over TCP
+    pub fn record_tcp_packet(&mut self, length: usize) {
+        self.tcp_total_packets += 1;
+        self.tcp_volume += length as u64;
+    }
+
+    pub fn get_udp_ping_avg(&self) -> f32 {
+        self.udp_ping_avg
+    }
+
+    pub fn get_udp_ping_var(&self) -> f32 {
+        self.udp_ping_var
+    }
+
+    pub fn get_udp_packets(&self) -> u32 {
+        self.udp_packets
+    }
+
+    pub fn get_tcp_ping_avg(&self) -> f32 {
+        self.tcp_ping_avg
+    }
+
+    pub fn get_tcp_ping_var(&self) -> f32 {
+        self.tcp_ping_var
+    }
+
+    pub fn get_tcp_packets(&self) -> u32 {
+        self.tcp_packets
+    }
+
+    pub fn get_tcp_volume(&self) -> u64 {
+        self.tcp_volume
+    }
+
+    pub fn get_remote_counters(&self) -> CryptoCounters {
+        self.remote_counters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ping_keeps_unreported_values() {
+        let mut stats = ClientStats::default();
+        stats.record_ping(&Ping {
+            good: Some(10),
+            lost: Some(2),
+            tcp_ping_avg: Some(12.5),
+            ..Default::default()
+        });
+        stats.record_ping(&Ping {
+            late: Some(1),
+            ..Default::default()
+        });
+
+        assert_eq!(stats.get_tcp_ping_avg(), 12.5);
+        assert_eq!(
+            stats.get_remote_counters(),
+            CryptoCounters { good: 10, late: 1, lost: 2, resync: 0 }
+        );
+    }
+}
@@ -1,12 +1,69 @@
-use std::{net::IpAddr, str::FromStr};
+use std::{borrow::Cow, net::IpAddr, str::FromStr};
 
 use cidr::{AnyIpCidr};
 
-enum IPMaskType<'a> {
+pub(crate) enum IPMaskType<'a> {
     FullMatch(IpAddr),
     CIDR(AnyIpCidr),
     ASN(u32),
-    CountryCode(&'a str),
+    CountryCode(Cow<'a, str>),
+}
+
+impl<'a> IPMaskType<'a> {
+    /// Parses the part of an IP database mask that follows the leading `%`
+    pub(crate) fn parse(mask: &'a str) -> Option<Self> {
+        match mask.chars().next() {
+            // Country code
+            Some('#') => Some(IPMaskType::CountryCode(Cow::Borrowed(&mask[1..]))),
+
+            // ASN
+            Some('@') => mask[1..].parse::<u32>().ok().map(IPMaskType::ASN),
+
+            // CIDR
+            Some('!') => AnyIpCidr::from_str(&mask[1..]).ok().map(IPMaskType::CIDR),
+
+            None => None,
+
+            // Full match
+            _ => mask.parse::<IpAddr>().ok().map(IPMaskType::FullMatch),
+        }
+    }
+
+    /// Detaches the mask from the string it was parsed from
+    pub(crate) fn into_owned(self) -> IPMaskType<'static> {
+        match self {
+            IPMaskType::FullMatch(ip) => IPMaskType::FullMatch(ip),
+            IPMaskType::CIDR(cidr) => IPMaskType::CIDR(cidr),
+            IPMaskType::ASN(asn) => IPMaskType::ASN(asn),
+            IPMaskType::CountryCode(cc) => IPMaskType::CountryCode(Cow::Owned(cc.into_owned())),
+        }
+    }
+
+    pub(crate) fn matches(
+        &self,
+        ip_address: Option<IpAddr>,
+        asn: Option<u32>,
+        country_code: Option<&str>,
+    ) -> bool {
+        match self {
+            IPMaskType::FullMatch(ip) => match ip_address {
+                Some(client_ip) => client_ip.to_canonical() == *ip,
+                None => false,
+            },
+            IPMaskType::CIDR(cidr) => match ip_address {
+                Some(client_ip) => cidr.contains(&client_ip.to_canonical()),
+                None => false,
+            },
+            IPMaskType::ASN(expected_asn) => match asn {
+                Some(client_asn) => client_asn == *expected_asn,
+                None => false,
+            },
+            IPMaskType::CountryCode(expected_cc) => match country_code {
+                Some(client_cc) => client_cc.eq_ignore_ascii_case(expected_cc),
+                None => false,
+            },
+        }
+    }
 }
 
 enum TokenMatchType<'a> {
@@ -39,6 +96,30 @@ pub struct ClientMembershipQuery<'a> {
     country_code: Option<&'a str>,
 }
 
+impl<'a> ClientMembershipQuery<'a> {
+    pub fn new(
+        groups: &'a [&'a str],
+        authenticated: bool,
+        access_tokens: &'a [&'a str],
+        cert_hash: Option<&'a [u8]>,
+        has_verified_cert_chain: bool,
+        ip_address: Option<IpAddr>,
+        asn: Option<u32>,
+        country_code: Option<&'a str>,
+    ) -> Self {
+        ClientMembershipQuery {
+            groups,
+            authenticated,
+            access_tokens,
+            cert_hash,
+            has_verified_cert_chain,
+            ip_address,
+            asn,
+            country_code,
+        }
+    }
+}
+
 pub fn is_member_in_group(
     group: &str,
     current_channel_id: u32,
@@ -84,33 +165,9 @@ pub fn is_member_in_group(
                 client.access_tokens.iter().any(|&t| t == token)
             }
         },
-        Some(MatchType::IPMask(ip_mask_type)) => match ip_mask_type {
-            IPMaskType::FullMatch(ip) => {
-                match client.ip_address {
-                    Some(client_ip) => client_ip == ip,
-                    None => false,
-                }
-            }
-            IPMaskType::CIDR(cidr) => {
-                match client.ip_address {
-                    Some(client_ip) => cidr.contains(&client_ip),
-                    None => false,
-                }
-            }
-            IPMaskType::ASN(asn) => {
-                match client.asn {
-                    Some(client_asn) => client_asn == asn,
-                    None => false,
-                }
-            }
-            IPMaskType::CountryCode(country_code) => {
-                match client.country_code {
-                    Some(client_cc) => client_cc.eq_ignore_ascii_case(country_code),
-                    None => false,
-                }
-            }
-        },
-
+        Some(MatchType::IPMask(ip_mask_type)) => {
+            ip_mask_type.matches(client.ip_address, client.asn, client.country_code)
+        }
     };
 
     if invert {
@@ -181,38 +238,15 @@ fn evaluate_group_string_match_type<'a>(group: &'a str) -> (Option<MatchType<'a>
 
             // IP Database Mask
             Some('%') => {
-                group_name_slice = &group_name_slice[1..];
-                match group_name_slice.chars().next() {
-                    // Country code
-                    Some('#') => {
-                        break Some(MatchType::IPMask(IPMaskType::CountryCode(
-                            &group_name_slice[1..],
-                        )));
-                    }
-
-                    // ASN
-                    Some('@') => match group_name_slice[1..].parse::<u32>() {
-                        Ok(asn) => break Some(MatchType::IPMask(IPMaskType::ASN(asn))),
-                        Err(_) => break None,
-                    },
-
-                    // CIDR
-                    Some('!') => {
-                        let cidr_str = &group_name_slice[1..];
-                        match AnyIpCidr::from_str(cidr_str) {
-                            Ok(cidr) => break Some(MatchType::IPMask(IPMaskType::CIDR(cidr))),
-                            Err(_) => break None,
-                        }
-                    }
-
-                    // Full match
-                    None => match group_name_slice[1..].parse::<IpAddr>() {
-                        Ok(ip) => break Some(MatchType::IPMask(IPMaskType::FullMatch(ip))),
-                        Err(_) => break None,
-                    },
+                break IPMaskType::parse(&group_name_slice[1..]).map(MatchType::IPMask);
+            }
 
-                    _ => break None,
-                }
+            // Built-in groups
+            _ if group_name_slice.eq_ignore_ascii_case("all") => break Some(MatchType::All),
+            _ if group_name_slice.eq_ignore_ascii_case("none") => break Some(MatchType::None),
+            _ if group_name_slice.eq_ignore_ascii_case("auth") => break Some(MatchType::Authenticated),
+            _ if group_name_slice.eq_ignore_ascii_case("strong") => {
+                break Some(MatchType::HasVerifiedCertificateChain)
             }
 
             _ => break Some(MatchType::ClientGroup(group_name_slice)),
@@ -1,7 +1,8 @@
-use std::collections::{HashSet};
-
+/// Mute, deafen and plugin state of a session.
+///
+/// The current channel and listeners live in `ClientGlobalState`.
+#[derive(Debug, Clone, Default)]
 pub struct SessionStates {
-    channel_id: u32,
     self_mute: bool,
     self_deaf: bool,
     mute: bool,
@@ -11,5 +12,103 @@ pub struct SessionStates {
     recording: bool,
     plugin_context: Vec<u8>,
     plugin_identity: String,
-    listening_channel_id: HashSet<u32>,
+}
+
+impl SessionStates {
+    pub fn new() -> Self {
+        SessionStates::default()
+    }
+
+    pub fn is_self_mute(&self) -> bool {
+        self.self_mute
+    }
+
+    /// Unmuting also undeafens
+    pub fn set_self_mute(&mut self, value: bool) {
+        self.self_mute = value;
+        if !value {
+            self.self_deaf = false;
+        }
+    }
+
+    pub fn is_self_deaf(&self) -> bool {
+        self.self_deaf
+    }
+
+    /// Deafening also mutes
+    pub fn set_self_deaf(&mut self, value: bool) {
+        self.self_deaf = value;
+        if value {
+            self.self_mute = true;
+        }
+    }
+
+    pub fn is_mute(&self) -> bool {
+        self.mute
+    }
+
+    /// Unmuting also undeafens
+    pub fn set_mute(&mut self, value: bool) {
+        self.mute = value;
+        if !value {
+            self.deaf = false;
+        }
+    }
+
+    pub fn is_deaf(&self) -> bool {
+        self.deaf
+    }
+
+    /// Deafening also mutes
+    pub fn set_deaf(&mut self, value: bool) {
+        self.deaf = value;
+        if value {
+            self.mute = true;
+        }
+    }
+
+    pub fn is_suppress(&self) -> bool {
+        self.suppress
+    }
+
+    pub fn set_suppress(&mut self, value: bool) {
+        self.suppress = value;
+    }
+
+    pub fn is_priority_speaker(&self) -> bool {
+        self.priority_speaker
+    }
+
+    pub fn set_priority_speaker(&mut self, value: bool) {
+        self.priority_speaker = value;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    pub fn set_recording(&mut self, value: bool) {
+        self.recording = value;
+    }
+
+    pub fn get_plugin_context(&self) -> &[u8] {
+        &self.plugin_context
+    }
+
+    pub fn set_plugin_context(&mut self, value: Vec<u8>) {
+        self.plugin_context = value;
+    }
+
+    pub fn get_plugin_identity(&self) -> &str {
+        &self.plugin_identity
+    }
+
+    pub fn set_plugin_identity(&mut self, value: String) {
+        self.plugin_identity = value;
+    }
+
+    /// Whether the session may not be heard, for whatever reason
+    pub fn is_silenced(&self) -> bool {
+        self.self_mute || self.mute || self.suppress
+    }
 }
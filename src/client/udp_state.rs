@@ -2,15 +2,60 @@ use std::collections::{HashMap};
 
 use chrono::{DateTime, Utc};
 
-use crate::{client::voice_target::VoiceTarget, voice_crypto::CryptoProvider};
+use crate::{bandwidth::BandwidthMeter, client::voice_target::VoiceTarget, voice_crypto::{CryptoCounters, CryptoProvider}};
 
 pub struct UdpState {
     udp_enabled: bool,
 
     last_resync: DateTime<Utc>,
-    crypto_provider: Box<dyn CryptoProvider>,
+    /// Set up once CryptSetup has been exchanged
+    crypto_provider: Option<Box<dyn CryptoProvider>>,
+    /// Packets received from the client
+    local_counters: CryptoCounters,
     celt_versions: Vec<i32>,
     opus: bool,
 
     voice_targets: HashMap<u32, VoiceTarget>,
+    /// Incoming voice, measured against the server bandwidth cap
+    bandwidth: BandwidthMeter,
+}
+
+impl UdpState {
+    pub fn new() -> Self {
+        UdpState {
+            udp_enabled: false,
+            last_resync: Utc::now(),
+            crypto_provider: None,
+            local_counters: CryptoCounters::default(),
+            celt_versions: Vec::new(),
+            opus: false,
+            voice_targets: HashMap::new(),
+            bandwidth: BandwidthMeter::default(),
+        }
+    }
+
+    pub fn get_local_counters(&self) -> CryptoCounters {
+        self.local_counters
+    }
+
+    pub fn get_celt_versions(&self) -> &[i32] {
+        &self.celt_versions
+    }
+
+    pub fn is_opus(&self) -> bool {
+        self.opus
+    }
+
+    pub fn set_codecs(&mut self, celt_versions: Vec<i32>, opus: bool) {
+        self.celt_versions = celt_versions;
+        self.opus = opus;
+    }
+
+    pub fn get_bandwidth_mut(&mut self) -> &mut BandwidthMeter {
+        &mut self.bandwidth
+    }
+
+    pub fn clear_voice_targets(&mut self) {
+        self.voice_targets.clear();
+    }
 }
@@ -40,6 +40,10 @@ impl UserInfo {
         &self.tokens
     }
 
+    pub fn set_tokens(&mut self, tokens: HashSet<String>) {
+        self.tokens = tokens;
+    }
+
     // TODO: case insensitive
     pub fn has_token(&self, token: &str) -> bool {
         self.tokens.contains(&token.to_string())
@@ -49,3 +53,17 @@ impl UserInfo {
         &self.display_name
     }
 }
+
+impl UserInfoExtended {
+    pub fn new(username: String, password: Option<String>) -> Self {
+        UserInfoExtended { username, password }
+    }
+
+    pub fn get_username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn get_password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+}
@@ -1,3 +1,6 @@
+use crate::mumble_proto::Version;
+use crate::protocol_version::ProtocolVersion;
+
 #[derive(Debug)]
 pub struct UserVersion {
     version: u32,
@@ -6,3 +9,39 @@ pub struct UserVersion {
     os_version: String,
     crypto_mode: String,
 }
+
+impl UserVersion {
+    pub fn get_version(&self) -> ProtocolVersion {
+        ProtocolVersion::from(self.version)
+    }
+
+    /// Version message as reported to others, e.g. in UserStats
+    pub fn to_version(&self) -> Version {
+        let version = self.get_version();
+        Version {
+            version_v1: Some(version.into()),
+            version_v2: Some(version.into()),
+            release: Some(self.client_name.clone()),
+            os: Some(self.os_name.clone()),
+            os_version: Some(self.os_version.clone()),
+        }
+    }
+}
+
+impl From<&Version> for UserVersion {
+    fn from(version: &Version) -> Self {
+        let protocol_version = match (version.version_v2, version.version_v1) {
+            (Some(v2), _) => ProtocolVersion::from(v2),
+            (None, Some(v1)) => ProtocolVersion::from(v1),
+            (None, None) => ProtocolVersion::from(0u32),
+        };
+
+        UserVersion {
+            version: protocol_version.into(),
+            client_name: version.release.clone().unwrap_or_default(),
+            os_name: version.os.clone().unwrap_or_default(),
+            os_version: version.os_version.clone().unwrap_or_default(),
+            crypto_mode: String::new(),
+        }
+    }
+}
@@ -1,11 +1,18 @@
-use rustls::{DigitallySignedStruct, DistinguishedName, Error, SignatureScheme, client::danger::HandshakeSignatureValid, pki_types::{CertificateDer, UnixTime}, server::danger::{ClientCertVerified, ClientCertVerifier}};
+use std::sync::Arc;
+
+use rustls::{DigitallySignedStruct, DistinguishedName, Error, RootCertStore, SignatureScheme, client::danger::HandshakeSignatureValid, crypto::{WebPkiSupportedAlgorithms, verify_tls12_signature, verify_tls13_signature}, pki_types::{CertificateDer, UnixTime, pem::PemObject as _}, server::{WebPkiClientVerifier, danger::{ClientCertVerified, ClientCertVerifier}}};
 
 #[derive(Debug)]
-pub struct ClientCertificateVerifier;
+pub struct ClientCertificateVerifier {
+    supported_algorithms: WebPkiSupportedAlgorithms,
+}
 
 impl ClientCertificateVerifier {
     pub fn new() -> Self {
-        ClientCertificateVerifier
+        ClientCertificateVerifier {
+            supported_algorithms: rustls::crypto::aws_lc_rs::default_provider()
+                .signature_verification_algorithms,
+        }
     }
 }
 
@@ -14,47 +21,79 @@ impl ClientCertVerifier for ClientCertificateVerifier {
         &[]
     }
 
+    // Any certificate is accepted during the handshake, including self-signed ones.
+    // Whether the chain is trusted is decided afterwards by `ClientChainValidator`.
     fn verify_client_cert(
         &self,
-        end_entity: &CertificateDer<'_>,
-        intermediates: &[CertificateDer<'_>],
-        now: UnixTime,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
     ) -> Result<ClientCertVerified, Error> {
         Ok(ClientCertVerified::assertion())
     }
-    
+
     fn verify_tls12_signature(
         &self,
         message: &[u8],
         cert: &CertificateDer<'_>,
         dss: &DigitallySignedStruct,
     ) -> Result<HandshakeSignatureValid, Error> {
-        Ok(HandshakeSignatureValid::assertion())
+        verify_tls12_signature(message, cert, dss, &self.supported_algorithms)
     }
-    
+
     fn verify_tls13_signature(
         &self,
         message: &[u8],
         cert: &CertificateDer<'_>,
         dss: &DigitallySignedStruct,
     ) -> Result<HandshakeSignatureValid, Error> {
-        Ok(HandshakeSignatureValid::assertion())
+        verify_tls13_signature(message, cert, dss, &self.supported_algorithms)
     }
 
     fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
-        vec![]
+        self.supported_algorithms.supported_schemes()
     }
 
     // Provided methods
-    fn offer_client_auth(&self) -> bool { 
+    fn offer_client_auth(&self) -> bool {
         true
     }
 
-    fn client_auth_mandatory(&self) -> bool { 
+    fn client_auth_mandatory(&self) -> bool {
         false
     }
 
-    fn requires_raw_public_keys(&self) -> bool { 
-        true
+    fn requires_raw_public_keys(&self) -> bool {
+        false
+    }
+}
+
+/// Validates client certificate chains against the configured CA bundle.
+pub struct ClientChainValidator {
+    verifier: Arc<dyn ClientCertVerifier>,
+}
+
+impl ClientChainValidator {
+    pub fn from_pem_file(ca_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut roots = RootCertStore::empty();
+        for certificate in CertificateDer::pem_file_iter(ca_path)? {
+            roots.add(certificate?)?;
+        }
+
+        let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+            .allow_unauthenticated()
+            .build()?;
+
+        Ok(ClientChainValidator { verifier })
+    }
+
+    pub fn is_trusted(&self, chain: &[CertificateDer<'_>]) -> bool {
+        match chain {
+            [end_entity, intermediates @ ..] => self
+                .verifier
+                .verify_client_cert(end_entity, intermediates, UnixTime::now())
+                .is_ok(),
+            [] => false,
+        }
     }
 }
@@ -12,14 +12,49 @@ use crate::{
     client::{
         client::Client, client_session_identifier::ClientSessionIdentifier,
     },
+    config::ConnectionLimitsConfig,
     constants::MAX_LOCAL_SESSION_ID,
+    geoip,
+    rate_limit::address_prefix,
 };
 
+/// A connection turned away because its host already holds too many sessions
+pub struct SessionLimitExceeded {
+    pub reason: &'static str,
+    pub connection: TlsStream<TcpStream>,
+}
+
+type HostSessions = HashMap<IpAddr, HashSet<ClientSessionIdentifier>>;
+
+/// Returns the reason when `address` may not open another session.
+/// Hosts are keyed by canonical address, so mapped IPv4 counts as IPv4.
+fn check_session_limits(hosts: &HostSessions, address: IpAddr, limits: &ConnectionLimitsConfig) -> Option<&'static str> {
+    if let Some(max) = limits.max_sessions_per_ip {
+        if hosts.get(&address).map_or(0, HashSet::len) >= max {
+            return Some("Too many connections from your address");
+        }
+    }
+
+    if let (Some(max), IpAddr::V6(_)) = (limits.max_sessions_per_ipv6_prefix, address) {
+        let prefix = address_prefix(address, 32, limits.ipv6_prefix_length);
+        let sessions: usize = hosts
+            .iter()
+            .filter(|(host, _)| prefix.contains(host))
+            .map(|(_, set)| set.len())
+            .sum();
+        if sessions >= max {
+            return Some("Too many connections from your network");
+        }
+    }
+
+    None
+}
+
 pub struct ClientRepository {
     local_node_id: u16,
     clients: RwLock<HashMap<ClientSessionIdentifier, Arc<Box<Client>>>>,
 
-    clients_by_host: RwLock<HashMap<IpAddr, HashSet<ClientSessionIdentifier>>>,
+    clients_by_host: RwLock<HostSessions>,
     clients_by_udp_address: RwLock<HashMap<SocketAddr, ClientSessionIdentifier>>,
 
     // The pointer only store local_session_id part
@@ -42,14 +77,24 @@ impl ClientRepository {
     pub async fn allocate_local_client(
         &self,
         real_ip_address: IpAddr,
+        geoip: geoip::Config,
         tcp_address: SocketAddr,
         udp_address: Option<SocketAddr>,
         local_address: SocketAddr,
         connection: TlsStream<TcpStream>,
-    ) -> Arc<Box<Client>> {
+        verified_certificate_chain: bool,
+        limits: &ConnectionLimitsConfig,
+    ) -> Result<Arc<Box<Client>>, SessionLimitExceeded> {
         let mut clients_guard = self.clients.write().await;
         let mut client_by_udp_address_guard = self.clients_by_udp_address.write().await;
         let mut client_by_host_guard = self.clients_by_host.write().await;
+
+        // Checked under the host lock so concurrent connects cannot overshoot the limits
+        let host = real_ip_address.to_canonical();
+        if let Some(reason) = check_session_limits(&client_by_host_guard, host, limits) {
+            return Err(SessionLimitExceeded { reason, connection });
+        }
+
         let mut free_ids_guard = self.free_ids.lock().await;
 
         let id = {
@@ -72,12 +117,14 @@ impl ClientRepository {
         let client = Client::new_local(
             client_identifier,
             real_ip_address,
+            geoip,
             tcp_address,
             udp_address,
             local_address,
             connection,
+            verified_certificate_chain,
         );
-        
+
         let client = Arc::new(client);
 
         clients_guard.insert(client_identifier, Arc::clone(&client));
@@ -87,15 +134,9 @@ impl ClientRepository {
                 .insert(udp_address, client_identifier);
         }
 
-        if let Some(set) = client_by_host_guard.get_mut(&tcp_address.ip()) {
-            set.insert(client_identifier);
-        } else {
-            let mut set = HashSet::new();
-            set.insert(client_identifier);
-            client_by_host_guard.insert(tcp_address.ip(), set);
-        }
+        client_by_host_guard.entry(host).or_default().insert(client_identifier);
 
-        client
+        Ok(client)
     }
 
     pub async fn add_remote_client(&self, id: ClientSessionIdentifier, client: Arc<Box<Client>>) {
@@ -119,12 +160,12 @@ impl ClientRepository {
                     client_by_udp_address_guard.remove(&udp_address);
                 }
 
-                let tcp_address = client.get_tcp_address();
+                let host = client.get_real_ip_address().to_canonical();
 
-                if let Some(set) = client_by_host_guard.get_mut(&tcp_address.ip()) {
+                if let Some(set) = client_by_host_guard.get_mut(&host) {
                     set.remove(&id);
                     if set.is_empty() {
-                        client_by_host_guard.remove(&tcp_address.ip());
+                        client_by_host_guard.remove(&host);
                     }
                 }
 
@@ -161,4 +202,39 @@ impl ClientRepository {
         self.clients.read().await.get(&id).cloned()
     }
 
+    pub async fn get_clients(&self) -> Vec<Arc<Box<Client>>> {
+        self.clients.read().await.values().cloned().collect()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts(entries: &[(&str, u32)]) -> HostSessions {
+        let mut hosts = HostSessions::new();
+        for (address, session) in entries {
+            let id = ClientSessionIdentifier::new(1, *session).unwrap();
+            hosts.entry(address.parse().unwrap()).or_default().insert(id);
+        }
+        hosts
+    }
+
+    #[test]
+    fn session_limits_per_host_and_prefix() {
+        let limits = ConnectionLimitsConfig {
+            max_sessions_per_ip: Some(2),
+            max_sessions_per_ipv6_prefix: Some(2),
+            ipv6_prefix_length: 64,
+            ..Default::default()
+        };
+        let hosts = hosts(&[("192.0.2.1", 1), ("192.0.2.1", 2), ("2001:db8::1", 3), ("2001:db8::2", 4)]);
+
+        let mapped: IpAddr = "::ffff:192.0.2.1".parse().unwrap();
+        assert!(check_session_limits(&hosts, mapped.to_canonical(), &limits).is_some());
+        assert!(check_session_limits(&hosts, "192.0.2.2".parse().unwrap(), &limits).is_none());
+        assert!(check_session_limits(&hosts, "2001:db8::3".parse().unwrap(), &limits).is_some());
+        assert!(check_session_limits(&hosts, "2001:db8:1::1".parse().unwrap(), &limits).is_none());
+    }
 }
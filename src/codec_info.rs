@@ -1,3 +1,17 @@
+use std::collections::BTreeMap;
+
+use crate::mumble_proto::CodecVersion;
+
+/// CELT 0.7.0, assumed for clients that announce no codec at all
+pub const CELT_0_7_0_BITSTREAM: i32 = 0x8000000bu32 as i32;
+
+/// Sent to clients that cannot decode Opus once the server switches to it
+pub const OPUS_WARNING: &str = "<strong>WARNING:</strong> Your client doesn't support the Opus codec the server is switching to, you won't be able to talk or hear anyone. Please upgrade to a client with Opus support.";
+
+/// Sent to Opus-only clients while the server uses a CELT version they lack
+pub const CELT_WARNING: &str = "<strong>WARNING:</strong> Your client doesn't support the CELT codec the server is using, you won't be able to talk or hear anyone. Please use a client with CELT support.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CodecInfo {
     alpha_codec: i32,
     beta_codec: i32,
@@ -16,3 +30,124 @@ impl Default for CodecInfo {
         }
     }
 }
+
+impl CodecInfo {
+    pub fn is_opus(&self) -> bool {
+        self.opus
+    }
+
+    /// Whether a client announcing `celt_versions` and `opus` can decode the chosen codec
+    pub fn can_decode(&self, celt_versions: &[i32], opus: bool) -> bool {
+        let current = match self.prefer_alpha_codec {
+            true => self.alpha_codec,
+            false => self.beta_codec,
+        };
+        match self.opus {
+            true => opus,
+            false => celt_versions.contains(&current),
+        }
+    }
+
+    pub fn to_codec_version(&self) -> CodecVersion {
+        CodecVersion {
+            alpha: self.alpha_codec,
+            beta: self.beta_codec,
+            prefer_alpha: self.prefer_alpha_codec,
+            opus: Some(self.opus),
+        }
+    }
+
+    /// Mumble's codec vote over the `(celt_versions, opus)` of every connected client.
+    /// Opus is used once `opus_threshold` percent support it, otherwise the most common
+    /// CELT version. Returns whether the result changed.
+    pub fn vote<'a>(
+        &mut self,
+        clients: impl IntoIterator<Item = (&'a [i32], bool)>,
+        opus_threshold: u16,
+    ) -> bool {
+        let mut users = 0u32;
+        let mut opus_users = 0u32;
+        let mut celt_users = BTreeMap::<i32, u32>::new();
+        for (celt_versions, opus) in clients {
+            users += 1;
+            if opus {
+                opus_users += 1;
+            }
+            for version in celt_versions {
+                *celt_users.entry(*version).or_default() += 1;
+            }
+        }
+
+        if users == 0 {
+            return false;
+        }
+        let opus = opus_users * 100 / users >= opus_threshold as u32;
+
+        let mut version = None;
+        let mut highest = 0;
+        for (&candidate, &count) in &celt_users {
+            if count > highest {
+                version = Some(candidate);
+                highest = count;
+            }
+        }
+
+        let current = match self.prefer_alpha_codec {
+            true => self.alpha_codec,
+            false => self.beta_codec,
+        };
+        match version {
+            Some(version) if version != current => {
+                // The compat bitstream always goes into alpha, others take the slot not in use
+                self.prefer_alpha_codec = match version {
+                    CELT_0_7_0_BITSTREAM => true,
+                    _ => !self.prefer_alpha_codec,
+                };
+                match self.prefer_alpha_codec {
+                    true => self.alpha_codec = version,
+                    false => self.beta_codec = version,
+                }
+            }
+            _ if self.opus == opus => return false,
+            _ => {}
+        }
+
+        self.opus = opus;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switches_to_opus_at_threshold() {
+        let celt: &[i32] = &[CELT_0_7_0_BITSTREAM];
+        let mut info = CodecInfo::default();
+
+        assert!(info.vote([(celt, true), (celt, false)], 100));
+        assert!(!info.is_opus());
+        assert_eq!(info.to_codec_version().alpha, CELT_0_7_0_BITSTREAM);
+        assert!(info.to_codec_version().prefer_alpha);
+
+        assert!(!info.vote([(celt, true), (celt, false)], 100));
+        assert!(info.vote([(celt, true), (celt, true)], 100));
+        assert!(info.is_opus());
+        assert!(!info.vote(std::iter::empty(), 100));
+    }
+
+    #[test]
+    fn decodability_covers_both_codecs() {
+        let celt: &[i32] = &[CELT_0_7_0_BITSTREAM];
+        let mut info = CodecInfo::default();
+
+        info.vote([(celt, false), (&[][..], true)], 100);
+        assert!(info.can_decode(celt, false));
+        assert!(!info.can_decode(&[], true));
+
+        info.vote([(celt, true), (&[][..], true)], 100);
+        assert!(info.can_decode(&[], true));
+        assert!(!info.can_decode(celt, false));
+    }
+}
@@ -15,16 +15,501 @@ pub struct Config {
     pub send_build_info: bool,
     pub send_os_info: bool,
     pub allowed_proxies: Vec<String>,
+
+    // Connection policies
+    pub server_password: Option<String>,
+    #[serde(default)]
+    pub require_certificate: bool,
+    #[serde(default)]
+    pub require_verified_certificate: bool,
+    pub client_ca_path: Option<String>,
+    #[serde(default)]
+    pub registered_users_only: bool,
+
+    pub registry_path: Option<String>,
+    pub ban_list_path: Option<String>,
+    pub geoip_city_path: Option<String>,
+    pub geoip_asn_path: Option<String>,
+    /// Directory for comments, textures and channel descriptions. Kept in memory only when unset.
+    pub blob_path: Option<String>,
+    /// Directory for offline messages, one file per recipient. Kept in memory only when unset.
+    pub mail_path: Option<String>,
+    /// JSON lines file recording kicks and bans
+    pub audit_log_path: Option<String>,
+
+    #[serde(default)]
+    pub admission: AdmissionConfig,
+    #[serde(default)]
+    pub limits: ConnectionLimitsConfig,
+    #[serde(default)]
+    pub keepalive: KeepaliveConfig,
+    #[serde(default)]
+    pub autoban: AutobanConfig,
+    #[serde(default)]
+    pub message_rate_limits: MessageRateLimitConfig,
+    #[serde(default)]
+    pub message_size_limits: MessageSizeLimitsConfig,
+    #[serde(default)]
+    pub plugin_data: PluginDataConfig,
+    #[serde(default)]
+    pub text_messages: TextMessageConfig,
+    #[serde(default)]
+    pub html: HtmlSanitizerConfig,
+    #[serde(default)]
+    pub chat_history: ChatHistoryConfig,
+    #[serde(default)]
+    pub mailbox: MailboxConfig,
+    #[serde(default)]
+    pub textures: TextureConfig,
+    #[serde(default)]
+    pub afk: AfkConfig,
+    #[serde(default)]
+    pub server_info: ServerInfoConfig,
+    #[serde(default)]
+    pub suggestions: SuggestionsConfig,
+    #[serde(default)]
+    pub voice_bandwidth: VoiceBandwidthConfig,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AdmissionAction {
+    #[default]
+    Allow,
+    Deny,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AdmissionRule {
+    pub action: AdmissionAction,
+    /// IP database mask in ACL group syntax, e.g. `%#CN`, `%@14061`,
+    /// `%!10.0.0.0/8` or `%192.0.2.1`. Prefix with `!` to invert.
+    pub mask: String,
+}
+
+/// Connection-level allow/deny rules evaluated before the TLS handshake.
+/// The first matching rule decides; `default_action` applies when none match.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct AdmissionConfig {
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub default_action: AdmissionAction,
+    #[serde(default)]
+    pub rules: Vec<AdmissionRule>,
 }
 
 impl Config {
     pub fn load() -> Self {
+        Self::try_load().unwrap()
+    }
+
+    pub fn try_load() -> Result<Self, config::ConfigError> {
         ConfigCrate::builder()
             .add_source(File::with_name("config"))
             .add_source(Environment::with_prefix("SHITSPEAK").separator("_"))
-            .build()
-            .unwrap()
+            .build()?
             .try_deserialize()
-            .unwrap()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ConnectionLimitsConfig {
+    /// Sessions allowed from a single address
+    pub max_sessions_per_ip: Option<usize>,
+    /// Sessions allowed from a single IPv6 prefix of `ipv6_prefix_length` bits
+    pub max_sessions_per_ipv6_prefix: Option<usize>,
+    pub ipv6_prefix_length: u8,
+    /// Connections that have not finished authenticating yet, server-wide
+    pub max_pending_connections: Option<usize>,
+    pub handshake_timeout_secs: u64,
+    pub authenticate_timeout_secs: u64,
+    /// New connections per minute from one source prefix
+    pub connection_rate_per_minute: Option<u32>,
+    pub connection_burst: u32,
+    pub ipv4_rate_prefix_length: u8,
+    pub ipv6_rate_prefix_length: u8,
+}
+
+impl Default for ConnectionLimitsConfig {
+    fn default() -> Self {
+        ConnectionLimitsConfig {
+            max_sessions_per_ip: None,
+            max_sessions_per_ipv6_prefix: None,
+            ipv6_prefix_length: 64,
+            max_pending_connections: None,
+            handshake_timeout_secs: 10,
+            authenticate_timeout_secs: 30,
+            connection_rate_per_minute: None,
+            connection_burst: 10,
+            ipv4_rate_prefix_length: 32,
+            ipv6_rate_prefix_length: 64,
+        }
+    }
+}
+
+/// Pings expected from authenticated clients
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct KeepaliveConfig {
+    /// Sessions are dropped when they have not pinged for this long
+    pub ping_timeout_secs: Option<u64>,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        KeepaliveConfig {
+            ping_timeout_secs: Some(30),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AutobanConfig {
+    pub enabled: bool,
+    /// Length of the sliding window the counters cover
+    pub window_secs: u64,
+    /// Connection attempts per window before a prefix is banned
+    pub max_connections: Option<u32>,
+    /// Failed authentications per window before a prefix is banned
+    pub max_failed_authentications: Option<u32>,
+    pub ban_duration_secs: u32,
+    pub ipv4_prefix_length: u8,
+    pub ipv6_prefix_length: u8,
+    /// CIDRs that are never banned automatically, in addition to `allowed_proxies`
+    pub allowlist: Vec<String>,
+}
+
+impl Default for AutobanConfig {
+    fn default() -> Self {
+        AutobanConfig {
+            enabled: false,
+            window_secs: 60,
+            max_connections: Some(60),
+            max_failed_authentications: Some(10),
+            ban_duration_secs: 600,
+            ipv4_prefix_length: 32,
+            ipv6_prefix_length: 64,
+            allowlist: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+/// Per-client limits on the control channel, one bucket per message category
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MessageRateLimitConfig {
+    pub enabled: bool,
+    pub text_message: RateLimit,
+    pub channel_state: RateLimit,
+    pub user_state: RateLimit,
+    pub query: RateLimit,
+    pub other: RateLimit,
+    /// Violations tolerated within `violation_window_secs` before disconnecting
+    pub max_violations: u32,
+    pub violation_window_secs: u64,
+}
+
+impl Default for MessageRateLimitConfig {
+    fn default() -> Self {
+        MessageRateLimitConfig {
+            enabled: false,
+            text_message: RateLimit { burst: 5, per_second: 1.0 },
+            channel_state: RateLimit { burst: 10, per_second: 2.0 },
+            user_state: RateLimit { burst: 20, per_second: 5.0 },
+            query: RateLimit { burst: 20, per_second: 5.0 },
+            other: RateLimit { burst: 50, per_second: 20.0 },
+            max_violations: 20,
+            violation_window_secs: 60,
+        }
+    }
+}
+
+/// Maximum encoded size of incoming messages, in bytes
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MessageSizeLimitsConfig {
+    // Before authentication
+    pub version: u32,
+    pub authenticate: u32,
+    pub ping: u32,
+    pub unauthenticated: u32,
+
+    // After authentication
+    pub udp_tunnel: u32,
+    pub text_message: u32,
+    pub user_state: u32,
+    /// Applies to UserState messages carrying a texture
+    pub texture: u32,
+    pub default: u32,
+}
+
+impl Default for MessageSizeLimitsConfig {
+    fn default() -> Self {
+        MessageSizeLimitsConfig {
+            version: 1024,
+            authenticate: 64 * 1024,
+            ping: 256,
+            unauthenticated: 1024,
+            udp_tunnel: 4 * 1024,
+            text_message: 256 * 1024,
+            user_state: 128 * 1024,
+            texture: 1024 * 1024,
+            default: 1024 * 1024,
+        }
+    }
+}
+
+/// Relaying of PluginDataTransmission messages between clients
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PluginDataConfig {
+    pub enabled: bool,
+    /// Largest `data` payload relayed, in bytes
+    pub max_payload_bytes: usize,
+    /// Messages a single sender may relay, enforced whenever relaying is enabled
+    pub rate: RateLimit,
+}
+
+impl Default for PluginDataConfig {
+    fn default() -> Self {
+        PluginDataConfig {
+            enabled: true,
+            max_payload_bytes: 1000,
+            rate: RateLimit { burst: 15, per_second: 4.0 },
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TextMessageConfig {
+    /// Maximum length of a text message, in bytes. 0 disables the limit.
+    pub max_length: usize,
+    /// Maximum length of a message containing images. 0 disables the limit.
+    pub max_image_length: usize,
+}
+
+impl TextMessageConfig {
+    /// Whether `message` fits the limit that applies to it
+    pub fn allows_length(&self, message: &str) -> bool {
+        let max_length = match message.to_ascii_lowercase().contains("<img") {
+            true => self.max_image_length,
+            false => self.max_length,
+        };
+        max_length == 0 || message.len() <= max_length
+    }
+}
+
+impl Default for TextMessageConfig {
+    fn default() -> Self {
+        TextMessageConfig {
+            max_length: 5000,
+            max_image_length: 128 * 1024,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HtmlPolicyConfig {
+    /// When disabled, all markup is stripped and only text is kept
+    pub allow_html: bool,
+    /// Inline `data:image/...` images. Remote images are never allowed.
+    pub allow_data_images: bool,
+}
+
+impl Default for HtmlPolicyConfig {
+    fn default() -> Self {
+        HtmlPolicyConfig {
+            allow_html: true,
+            allow_data_images: true,
+        }
+    }
+}
+
+/// Sanitization policy per kind of user supplied HTML
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct HtmlSanitizerConfig {
+    pub text_message: HtmlPolicyConfig,
+    pub user_comment: HtmlPolicyConfig,
+    pub channel_description: HtmlPolicyConfig,
+}
+
+/// Channel messages replayed to users entering a channel
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ChatHistoryConfig {
+    pub enabled: bool,
+    /// Where history is persisted. Kept in memory only when unset.
+    pub path: Option<String>,
+    /// Messages kept per channel
+    pub max_messages: usize,
+    pub max_age_secs: u64,
+}
+
+impl Default for ChatHistoryConfig {
+    fn default() -> Self {
+        ChatHistoryConfig {
+            enabled: false,
+            path: None,
+            max_messages: 20,
+            max_age_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Offline messages for registered users
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MailboxConfig {
+    pub enabled: bool,
+    /// Entries a single mailbox holds
+    pub max_messages: usize,
+    pub max_age_secs: u64,
+}
+
+impl Default for MailboxConfig {
+    fn default() -> Self {
+        MailboxConfig {
+            enabled: false,
+            max_messages: 50,
+            max_age_secs: 30 * 24 * 60 * 60,
+        }
+    }
+}
+
+/// Avatar uploads
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TextureConfig {
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Largest stored texture, in bytes
+    pub max_bytes: usize,
+    /// Downscale and re-encode images over the limits instead of rejecting them
+    pub transcode: bool,
+    /// Raw 600x60 BGRA textures sent by pre-1.2.4 clients
+    pub allow_legacy: bool,
+}
+
+impl Default for TextureConfig {
+    fn default() -> Self {
+        TextureConfig {
+            max_width: 512,
+            max_height: 512,
+            max_bytes: 128 * 1024,
+            transcode: true,
+            allow_legacy: true,
+        }
+    }
+}
+
+/// Idle user handling
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AfkConfig {
+    pub enabled: bool,
+    /// Where idle users are moved. Without it users are only disconnected.
+    pub channel_id: Option<u32>,
+    /// Idle time before moving a user to the AFK channel
+    pub idle_secs: u64,
+    /// Self-deafen users when moving them
+    pub deafen: bool,
+    /// Further idle time after the move before disconnecting
+    pub disconnect_after_secs: Option<u64>,
+    pub exempt_registered: bool,
+    pub exempt_groups: Vec<String>,
+}
+
+impl Default for AfkConfig {
+    fn default() -> Self {
+        AfkConfig {
+            enabled: false,
+            channel_id: None,
+            idle_secs: 30 * 60,
+            deafen: false,
+            disconnect_after_secs: None,
+            exempt_registered: false,
+            exempt_groups: Vec::new(),
+        }
+    }
+}
+
+/// Announced to clients in ServerSync and ServerConfig. Text limits and HTML support are
+/// taken from `text_messages` and `html`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ServerInfoConfig {
+    /// Voice bandwidth a client may use, in bits per second
+    pub max_bandwidth: u32,
+    /// HTML shown to users when they connect
+    pub welcome_text: Option<String>,
+    /// File holding the welcome text. Takes precedence over `welcome_text`.
+    pub welcome_text_path: Option<String>,
+    /// Authenticated users allowed at once
+    pub max_users: Option<u32>,
+    pub recording_allowed: bool,
+}
+
+impl Default for ServerInfoConfig {
+    fn default() -> Self {
+        ServerInfoConfig {
+            max_bandwidth: 558000,
+            welcome_text: None,
+            welcome_text_path: None,
+            max_users: None,
+            recording_allowed: true,
+        }
+    }
+}
+
+/// Client settings suggested in SuggestConfig. Unset keys are not suggested.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SuggestionsConfig {
+    /// Minimum client version, e.g. `1.5.0`
+    pub version: Option<String>,
+    pub positional: Option<bool>,
+    pub push_to_talk: Option<bool>,
+}
+
+/// Enforcement of `server_info.max_bandwidth` on incoming voice
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct VoiceBandwidthConfig {
+    pub enabled: bool,
+    /// Length of the sliding window the bitrate is averaged over
+    pub window_ms: u64,
+    /// Headroom above the advertised bandwidth before packets are dropped
+    pub tolerance_percent: u32,
+    /// Dropped packets before the sender is warned, once per session
+    pub warn_after_drops: u32,
+}
+
+impl VoiceBandwidthConfig {
+    /// Highest accepted rate in bytes per second for an advertised bandwidth in bits per second
+    pub fn max_bytes_per_sec(&self, max_bandwidth: u32) -> u32 {
+        (max_bandwidth as u64 / 8 * (100 + self.tolerance_percent as u64) / 100).min(u32::MAX as u64) as u32
+    }
+}
+
+impl Default for VoiceBandwidthConfig {
+    fn default() -> Self {
+        VoiceBandwidthConfig {
+            enabled: true,
+            window_ms: 2000,
+            tolerance_percent: 20,
+            warn_after_drops: 50,
+        }
     }
 }
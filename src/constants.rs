@@ -4,6 +4,11 @@ use crate::protocol_version::ProtocolVersion;
 pub const MAX_NODE_ID: u16 = 0x0FFF;
 pub const MAX_LOCAL_SESSION_ID: u32 = 0x0FFFFF;
 pub const MTU: usize = 1600;
+pub const GEOIP_RELOAD_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+pub const RATE_LIMITER_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+pub const BAN_PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+pub const AFK_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+pub const BLOB_COLLECT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(600);
 
 pub const APP_NAME_FROM_ENV: Option<&str> = option_env!("APP_NAME");
 pub const APP_VERSION_FROM_ENV: Option<&str> = option_env!("APP_VERSION");
@@ -1,14 +1,269 @@
-use std::net::IpAddr;
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
-#[derive(Debug)]
+use maxminddb::{geoip2, Mmap, Reader};
+use tokio::sync::RwLock;
+
+#[derive(Debug, Clone, Default)]
 pub struct Config {
-    ip_address: IpAddr,
-    country_code: String,
-    country: String,
-    continent_code: String,
-    latitude: f64,
-    longitude: f64,
-    asn: i32,
-    organization: String,
-    timezone: i8,
+    pub ip_address: Option<IpAddr>,
+    pub country_code: Option<String>,
+    pub country: Option<String>,
+    pub continent_code: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub asn: Option<u32>,
+    pub organization: Option<String>,
+    pub timezone: Option<String>,
+}
+
+struct Database {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    reader: Reader<Mmap>,
+}
+
+/// Country/city and ASN lookups backed by MaxMind databases.
+///
+/// Either database is optional. Files are memory mapped and reopened by
+/// `reload_if_changed` whenever their modification time moves.
+pub struct GeoIpService {
+    city: Option<RwLock<Database>>,
+    asn: Option<RwLock<Database>>,
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Database {
+    fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = PathBuf::from(path);
+        let modified = modified_time(&path);
+        let reader = Reader::open_mmap(&path)?;
+        Ok(Database {
+            path,
+            modified,
+            reader,
+        })
+    }
+
+    fn reload_if_changed(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return Ok(false);
+        }
+
+        self.reader = Reader::open_mmap(&self.path)?;
+        self.modified = modified;
+        Ok(true)
+    }
+}
+
+impl GeoIpService {
+    pub fn open(
+        city_path: Option<&str>,
+        asn_path: Option<&str>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(GeoIpService {
+            city: city_path.map(Database::open).transpose()?.map(RwLock::new),
+            asn: asn_path.map(Database::open).transpose()?.map(RwLock::new),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.city.is_some() || self.asn.is_some()
+    }
+
+    pub async fn lookup(&self, ip_address: IpAddr) -> Config {
+        let ip_address = ip_address.to_canonical();
+        let mut info = Config {
+            ip_address: Some(ip_address),
+            ..Default::default()
+        };
+
+        if let Some(city) = &self.city {
+            let guard = city.read().await;
+            if let Ok(Some(record)) = guard.reader.lookup::<geoip2::City>(ip_address) {
+                if let Some(country) = record.country {
+                    info.country_code = country.iso_code.map(str::to_string);
+                    info.country = country
+                        .names
+                        .and_then(|names| names.get("en").map(|name| name.to_string()));
+                }
+                if let Some(continent) = record.continent {
+                    info.continent_code = continent.code.map(str::to_string);
+                }
+                if let Some(location) = record.location {
+                    info.latitude = location.latitude;
+                    info.longitude = location.longitude;
+                    info.timezone = location.time_zone.map(str::to_string);
+                }
+            }
+        }
+
+        if let Some(asn) = &self.asn {
+            let guard = asn.read().await;
+            if let Ok(Some(record)) = guard.reader.lookup::<geoip2::Asn>(ip_address) {
+                info.asn = record.autonomous_system_number;
+                info.organization = record.autonomous_system_organization.map(str::to_string);
+            }
+        }
+
+        info
+    }
+
+    pub async fn reload_if_changed(&self) {
+        for database in [&self.city, &self.asn].into_iter().flatten() {
+            let mut guard = database.write().await;
+            match guard.reload_if_changed() {
+                Ok(true) => tracing::info!(path = %guard.path.display(), "Reloaded GeoIP database"),
+                Ok(false) => {}
+                Err(e) => tracing::error!(path = %guard.path.display(), "Failed to reload GeoIP database: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Minimal MaxMind DB writer: an IPv4 tree with a single node whose left
+    // branch (0.0.0.0/1) points at `data` and whose right branch is empty.
+    fn encode_string(value: &str) -> Vec<u8> {
+        let mut out = match value.len() {
+            len if len < 29 => vec![0x40 | len as u8],
+            len => vec![0x40 | 29, (len - 29) as u8],
+        };
+        out.extend_from_slice(value.as_bytes());
+        out
+    }
+
+    fn encode_u16(value: u16) -> Vec<u8> {
+        let mut out = vec![0xA0 | 2];
+        out.extend_from_slice(&value.to_be_bytes());
+        out
+    }
+
+    fn encode_u32(value: u32) -> Vec<u8> {
+        let mut out = vec![0xC0 | 4];
+        out.extend_from_slice(&value.to_be_bytes());
+        out
+    }
+
+    fn encode_map(entries: Vec<(&str, Vec<u8>)>) -> Vec<u8> {
+        let mut out = vec![0xE0 | entries.len() as u8];
+        for (key, value) in entries {
+            out.extend(encode_string(key));
+            out.extend(value);
+        }
+        out
+    }
+
+    fn database(database_type: &str, data: Vec<u8>) -> Vec<u8> {
+        // One node of two 24-bit records: data pointer, then "no data"
+        let mut out = vec![0, 0, 17, 0, 0, 1];
+        out.extend([0; 16]);
+        out.extend(data);
+        out.extend(b"\xAB\xCD\xEFMaxMind.com");
+        out.extend(encode_map(vec![
+            ("node_count", encode_u32(1)),
+            ("record_size", encode_u16(24)),
+            ("ip_version", encode_u16(4)),
+            ("database_type", encode_string(database_type)),
+            ("languages", vec![0x00, 0x04]),
+            ("binary_format_major_version", encode_u16(2)),
+            ("binary_format_minor_version", encode_u16(0)),
+            ("build_epoch", vec![0x00, 0x02]),
+            ("description", vec![0xE0]),
+        ]));
+        out
+    }
+
+    fn asn_database(asn: u32, organization: &str) -> Vec<u8> {
+        database(
+            "GeoLite2-ASN",
+            encode_map(vec![
+                ("autonomous_system_number", encode_u32(asn)),
+                ("autonomous_system_organization", encode_string(organization)),
+            ]),
+        )
+    }
+
+    fn city_database(country_code: &str) -> Vec<u8> {
+        database(
+            "GeoLite2-City",
+            encode_map(vec![("country", encode_map(vec![("iso_code", encode_string(country_code))]))]),
+        )
+    }
+
+    /// Replaces the file through a rename so the old mapping stays valid
+    fn write_database(path: &Path, content: &[u8], modified: SystemTime) {
+        let staging = path.with_extension("tmp");
+        std::fs::write(&staging, content).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&staging)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        std::fs::rename(&staging, path).unwrap();
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("shitspeak-geoip-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn looks_up_country_and_asn() {
+        let dir = test_dir("lookup");
+        let city_path = dir.join("city.mmdb");
+        let asn_path = dir.join("asn.mmdb");
+        write_database(&city_path, &city_database("NL"), SystemTime::now());
+        write_database(&asn_path, &asn_database(64496, "Example Networks"), SystemTime::now());
+
+        let service = GeoIpService::open(city_path.to_str(), asn_path.to_str()).unwrap();
+        assert!(service.is_enabled());
+
+        let info = service.lookup("::ffff:10.0.0.1".parse().unwrap()).await;
+        assert_eq!(info.ip_address, Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(info.country_code.as_deref(), Some("NL"));
+        assert_eq!(info.asn, Some(64496));
+        assert_eq!(info.organization.as_deref(), Some("Example Networks"));
+
+        let unknown = service.lookup("192.0.2.1".parse().unwrap()).await;
+        assert_eq!(unknown.country_code, None);
+        assert_eq!(unknown.asn, None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reloads_when_modification_time_changes() {
+        let dir = test_dir("reload");
+        let path = dir.join("asn.mmdb");
+        let start = SystemTime::now() - std::time::Duration::from_secs(3600);
+        write_database(&path, &asn_database(64496, "Before"), start);
+
+        let service = GeoIpService::open(None, path.to_str()).unwrap();
+        let address: IpAddr = "10.0.0.1".parse().unwrap();
+        assert_eq!(service.lookup(address).await.asn, Some(64496));
+
+        service.reload_if_changed().await;
+        assert_eq!(service.lookup(address).await.asn, Some(64496));
+
+        write_database(&path, &asn_database(64497, "After"), start + std::time::Duration::from_secs(60));
+        service.reload_if_changed().await;
+        let info = service.lookup(address).await;
+        assert_eq!(info.asn, Some(64497));
+        assert_eq!(info.organization.as_deref(), Some("After"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,181 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+};
+
+use ammonia::{Builder, UrlRelative};
+
+use crate::config::{HtmlPolicyConfig, HtmlSanitizerConfig};
+
+/// Tags Mumble clients produce in rich text
+const ALLOWED_TAGS: &[&str] = &[
+    "a", "b", "blockquote", "br", "center", "code", "div", "em", "font", "h1", "h2", "h3", "h4",
+    "h5", "h6", "hr", "i", "img", "li", "ol", "p", "pre", "s", "small", "span", "strong", "sub",
+    "sup", "table", "tbody", "td", "th", "thead", "tr", "u", "ul",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    TextMessage,
+    UserComment,
+    ChannelDescription,
+}
+
+struct HtmlPolicy {
+    builder: Option<Builder<'static>>,
+}
+
+pub struct HtmlSanitizer {
+    text_message: HtmlPolicy,
+    user_comment: HtmlPolicy,
+    channel_description: HtmlPolicy,
+    plain_text: Builder<'static>,
+}
+
+fn filter_attribute<'u>(
+    element: &str,
+    attribute: &str,
+    value: &'u str,
+    allow_data_images: bool,
+) -> Option<Cow<'u, str>> {
+    let lowercase = value.trim().to_ascii_lowercase();
+    match (element, attribute) {
+        // Only inline images; anything remote could be a tracking pixel
+        ("img", "src") => (allow_data_images && lowercase.starts_with("data:image/")).then_some(value.into()),
+        (_, "href") => (!lowercase.starts_with("data:")).then_some(value.into()),
+        // CSS can load remote resources too
+        (_, "style") => (!lowercase.contains("url(") && !lowercase.contains("expression(")).then_some(value.into()),
+        _ => Some(value.into()),
+    }
+}
+
+impl HtmlPolicy {
+    fn new(config: &HtmlPolicyConfig) -> Self {
+        if !config.allow_html {
+            return HtmlPolicy { builder: None };
+        }
+
+        let mut tags = ALLOWED_TAGS.iter().copied().collect::<HashSet<_>>();
+        if !config.allow_data_images {
+            tags.remove("img");
+        }
+
+        let allow_data_images = config.allow_data_images;
+        let mut builder = Builder::default();
+        builder
+            .tags(tags)
+            .generic_attributes(HashSet::from(["style", "align"]))
+            .tag_attributes(HashMap::from([
+                ("a", HashSet::from(["href"])),
+                ("font", HashSet::from(["color", "size", "face"])),
+                ("img", HashSet::from(["src", "alt", "width", "height"])),
+                ("td", HashSet::from(["colspan", "rowspan"])),
+                ("th", HashSet::from(["colspan", "rowspan"])),
+            ]))
+            .url_schemes(HashSet::from(["http", "https", "mailto", "mumble", "data"]))
+            .url_relative(UrlRelative::Deny)
+            .attribute_filter(move |element, attribute, value| {
+                filter_attribute(element, attribute, value, allow_data_images)
+            });
+
+        HtmlPolicy {
+            builder: Some(builder),
+        }
+    }
+}
+
+impl HtmlSanitizer {
+    pub fn new(config: &HtmlSanitizerConfig) -> Self {
+        HtmlSanitizer {
+            text_message: HtmlPolicy::new(&config.text_message),
+            user_comment: HtmlPolicy::new(&config.user_comment),
+            channel_description: HtmlPolicy::new(&config.channel_description),
+            plain_text: Builder::empty(),
+        }
+    }
+
+    /// Cleans `input` according to the policy of `kind`.
+    ///
+    /// Returns `None` when non-empty input has nothing left after cleaning.
+    pub fn sanitize(&self, kind: ContentKind, input: &str) -> Option<String> {
+        let policy = match kind {
+            ContentKind::TextMessage => &self.text_message,
+            ContentKind::UserComment => &self.user_comment,
+            ContentKind::ChannelDescription => &self.channel_description,
+        };
+
+        let builder = policy.builder.as_ref().unwrap_or(&self.plain_text);
+        let output = builder.clean(input).to_string();
+
+        let has_content = output.contains("<img") || !self.plain_text.clean(&output).to_string().trim().is_empty();
+        if !has_content && !input.trim().is_empty() {
+            return None;
+        }
+
+        Some(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sanitizer(allow_html: bool, allow_data_images: bool) -> HtmlSanitizer {
+        let policy = HtmlPolicyConfig {
+            allow_html,
+            allow_data_images,
+        };
+        HtmlSanitizer::new(&HtmlSanitizerConfig {
+            text_message: policy.clone(),
+            user_comment: policy.clone(),
+            channel_description: policy,
+        })
+    }
+
+    #[test]
+    fn strips_scripts_and_event_handlers() {
+        let output = sanitizer(true, true)
+            .sanitize(
+                ContentKind::TextMessage,
+                r#"<b onclick="alert(1)">hi</b><script>alert(2)</script>"#,
+            )
+            .unwrap();
+        assert_eq!(output, "<b>hi</b>");
+    }
+
+    #[test]
+    fn strips_remote_images() {
+        let sanitizer = sanitizer(true, true);
+        let output = sanitizer
+            .sanitize(
+                ContentKind::TextMessage,
+                r#"hello<img src="https://tracker.example/p.gif"><span style="background: url(https://tracker.example/)">x</span>"#,
+            )
+            .unwrap();
+        assert!(!output.contains("tracker"));
+
+        let inline = r#"<img src="data:image/png;base64,AAAA">"#;
+        assert!(sanitizer
+            .sanitize(ContentKind::TextMessage, inline)
+            .unwrap()
+            .contains("data:image/png"));
+    }
+
+    #[test]
+    fn disallowed_images_leave_nothing() {
+        let inline = r#"<img src="data:image/png;base64,AAAA">"#;
+        assert_eq!(sanitizer(true, false).sanitize(ContentKind::UserComment, inline), None);
+        assert_eq!(
+            sanitizer(true, false).sanitize(ContentKind::UserComment, ""),
+            Some(String::new())
+        );
+    }
+
+    #[test]
+    fn plain_text_policy_drops_tags() {
+        let output = sanitizer(false, true)
+            .sanitize(ContentKind::ChannelDescription, "<i>a</i> &amp; b")
+            .unwrap();
+        assert_eq!(output, "a &amp; b");
+    }
+}
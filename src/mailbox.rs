@@ -0,0 +1,363 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{
+    acl::ACLPermissions, channels::ROOT_CHANNEL_ID, client::client::Client, messages::Message,
+    mumble_proto::TextMessage, server::Server,
+};
+
+pub const MAIL_COMMAND: &str = "/mail";
+
+const USAGE: &str = "Usage: /mail send &lt;user ID or name&gt; &lt;message&gt;, /mail list, /mail clear";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MailboxEntry {
+    pub sender_name: String,
+    pub sender_user_id: Option<u32>,
+    pub message: String,
+    pub time: DateTime<Utc>,
+    /// Set once the entry has been pushed to the recipient on login
+    #[serde(default)]
+    pub delivered: bool,
+}
+
+/// Offline messages of registered users, keyed by user ID.
+///
+/// Kept apart from the user registry so storing a message only rewrites the
+/// recipient's `<directory>/<user ID>.json` instead of every registered user.
+pub struct MailStore {
+    directory: Option<PathBuf>,
+    mailboxes: RwLock<HashMap<u32, Vec<MailboxEntry>>>,
+}
+
+impl MailStore {
+    pub fn open(directory: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        let Some(directory) = directory.map(PathBuf::from) else {
+            return Ok(MailStore {
+                directory: None,
+                mailboxes: RwLock::new(HashMap::new()),
+            });
+        };
+        std::fs::create_dir_all(&directory)?;
+
+        let mut mailboxes = HashMap::new();
+        for file in std::fs::read_dir(&directory)? {
+            let path = file?.path();
+            let user_id = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".json"))
+                .and_then(|id| id.parse::<u32>().ok());
+            if let Some(user_id) = user_id {
+                let entries: Vec<MailboxEntry> = serde_json::from_slice(&std::fs::read(&path)?)?;
+                mailboxes.insert(user_id, entries);
+            }
+        }
+
+        Ok(MailStore {
+            directory: Some(directory),
+            mailboxes: RwLock::new(mailboxes),
+        })
+    }
+
+    async fn save(&self, user_id: u32, entries: &[MailboxEntry]) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(directory) = &self.directory else {
+            return Ok(());
+        };
+
+        let path = directory.join(format!("{}.json", user_id));
+        match entries.is_empty() {
+            true => match tokio::fs::remove_file(&path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            },
+            false => Ok(tokio::fs::write(&path, serde_json::to_vec_pretty(entries)?).await?),
+        }
+    }
+
+    /// Stores a message for an offline user. Returns `false` when the mailbox is full.
+    pub async fn add(
+        &self,
+        user_id: u32,
+        entry: MailboxEntry,
+        max_messages: usize,
+        max_age: chrono::Duration,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut guard = self.mailboxes.write().await;
+        let mailbox = guard.entry(user_id).or_default();
+
+        mailbox.retain(|e| e.time + max_age > entry.time);
+        if mailbox.len() >= max_messages {
+            return Ok(false);
+        }
+
+        mailbox.push(entry);
+        self.save(user_id, mailbox).await?;
+        Ok(true)
+    }
+
+    /// Unexpired mail of a user, oldest first
+    pub async fn get(&self, user_id: u32, max_age: chrono::Duration) -> Vec<MailboxEntry> {
+        let now = Utc::now();
+        match self.mailboxes.read().await.get(&user_id) {
+            Some(mailbox) => mailbox.iter().filter(|e| e.time + max_age > now).cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns mail not yet delivered and marks it delivered. Expired entries are dropped.
+    pub async fn take_undelivered(
+        &self,
+        user_id: u32,
+        max_age: chrono::Duration,
+    ) -> Result<Vec<MailboxEntry>, Box<dyn std::error::Error>> {
+        let now = Utc::now();
+        let mut guard = self.mailboxes.write().await;
+        let Some(mailbox) = guard.get_mut(&user_id) else {
+            return Ok(Vec::new());
+        };
+
+        let before = mailbox.len();
+        mailbox.retain(|e| e.time + max_age > now);
+        let expired = before != mailbox.len();
+
+        let mut undelivered = Vec::new();
+        for entry in mailbox.iter_mut().filter(|e| !e.delivered) {
+            entry.delivered = true;
+            undelivered.push(entry.clone());
+        }
+
+        if expired || !undelivered.is_empty() {
+            self.save(user_id, mailbox).await?;
+        }
+        Ok(undelivered)
+    }
+
+    /// Empties a user's mailbox and returns how many entries were removed
+    pub async fn clear(&self, user_id: u32) -> Result<usize, Box<dyn std::error::Error>> {
+        let Some(mailbox) = self.mailboxes.write().await.remove(&user_id) else {
+            return Ok(0);
+        };
+
+        self.save(user_id, &[]).await?;
+        Ok(mailbox.len())
+    }
+}
+
+pub fn is_mail_command(message: &str) -> bool {
+    let message = message.trim_start();
+    message
+        .strip_prefix(MAIL_COMMAND)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+/// Splits off the first whitespace separated word
+fn split_word(input: &str) -> (&str, &str) {
+    let input = input.trim_start();
+    match input.find(char::is_whitespace) {
+        Some(index) => (&input[..index], input[index..].trim_start()),
+        None => (input, ""),
+    }
+}
+
+fn format_entry(entry: &MailboxEntry) -> String {
+    format!(
+        "<i>[offline message from {}, {}]</i> {}",
+        ammonia::clean_text(&entry.sender_name),
+        entry.time.format("%Y-%m-%d %H:%M UTC"),
+        entry.message
+    )
+}
+
+pub async fn handle_mail_command(
+    server: &Server,
+    client: &Client,
+    message: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = server.get_mailbox_config();
+    if !config.enabled {
+        return client.send_server_message("Offline messages are disabled on this server").await;
+    }
+
+    let max_age = chrono::Duration::seconds(config.max_age_secs as i64);
+    let registry = server.get_registry();
+    let mail = server.get_mail_store();
+    let (_, args) = split_word(message);
+    let (subcommand, args) = split_word(args);
+
+    match subcommand {
+        "send" => {
+            let (target, body) = split_word(args);
+            if target.is_empty() || body.is_empty() {
+                return client.send_server_message(USAGE).await;
+            }
+            if !server.has_permission(client, ROOT_CHANNEL_ID, ACLPermissions::TextMessage).await {
+                return client.send_permission_denied(ROOT_CHANNEL_ID, ACLPermissions::TextMessage).await;
+            }
+
+            let recipient = match target.parse::<u32>() {
+                Ok(user_id) => registry.get(user_id).await,
+                Err(_) => registry.get_by_name(target).await,
+            };
+            let Some(recipient) = recipient else {
+                return client
+                    .send_server_message(&format!("No registered user {}", ammonia::clean_text(target)))
+                    .await;
+            };
+
+            let sender_name = client.get_username().await.unwrap_or_default();
+
+            // Online recipients get the message right away
+            for other in server.get_clients().get_clients().await {
+                if other.is_authenticated().await && other.get_user_id().await == Some(recipient.user_id) {
+                    other
+                        .write_proto_message(&Message::TextMessage(TextMessage {
+                            actor: Some(client.get_session_id()),
+                            session: vec![other.get_session_id()],
+                            channel_id: Vec::new(),
+                            tree_id: Vec::new(),
+                            message: body.to_string(),
+                        }))
+                        .await?;
+                    return client.send_server_message("Message delivered").await;
+                }
+            }
+
+            let entry = MailboxEntry {
+                sender_name,
+                sender_user_id: client.get_user_id().await,
+                message: body.to_string(),
+                time: Utc::now(),
+                delivered: false,
+            };
+            let stored = mail.add(recipient.user_id, entry, config.max_messages, max_age).await?;
+            match stored {
+                true => {
+                    tracing::info!(
+                        session_id = client.get_session_id(),
+                        recipient = recipient.user_id,
+                        "Stored offline message"
+                    );
+                    client.send_server_message("Message stored for delivery").await
+                }
+                false => client.send_server_message("The recipient's mailbox is full").await,
+            }
+        }
+        "list" => {
+            let Some(user_id) = client.get_user_id().await else {
+                return client.send_server_message("Only registered users have a mailbox").await;
+            };
+
+            let entries = mail.get(user_id, max_age).await;
+            client
+                .send_server_message(&format!("You have {} offline message(s)", entries.len()))
+                .await?;
+            for entry in entries {
+                client.send_server_message(&format_entry(&entry)).await?;
+            }
+            Ok(())
+        }
+        "clear" => {
+            let Some(user_id) = client.get_user_id().await else {
+                return client.send_server_message("Only registered users have a mailbox").await;
+            };
+
+            let cleared = mail.clear(user_id).await?;
+            client
+                .send_server_message(&format!("Removed {} offline message(s)", cleared))
+                .await
+        }
+        _ => client.send_server_message(USAGE).await,
+    }
+}
+
+/// Pushes mail that arrived while the client was offline
+pub async fn deliver_mail(server: &Server, client: &Client) -> Result<(), Box<dyn std::error::Error>> {
+    let config = server.get_mailbox_config();
+    let Some(user_id) = client.get_user_id().await else {
+        return Ok(());
+    };
+    if !config.enabled {
+        return Ok(());
+    }
+
+    let max_age = chrono::Duration::seconds(config.max_age_secs as i64);
+    let entries = server.get_mail_store().take_undelivered(user_id, max_age).await?;
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    for entry in &entries {
+        client.send_server_message(&format_entry(entry)).await?;
+    }
+    client
+        .send_server_message("Use /mail list to read your messages again and /mail clear to delete them")
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert!(is_mail_command("  /mail list"));
+        assert!(is_mail_command("/mail"));
+        assert!(!is_mail_command("/mailbox"));
+
+        let (_, args) = split_word("/mail send  42   hello  there");
+        let (subcommand, args) = split_word(args);
+        assert_eq!(subcommand, "send");
+        assert_eq!(split_word(args), ("42", "hello  there"));
+    }
+
+    fn mail(message: &str, time: DateTime<Utc>) -> MailboxEntry {
+        MailboxEntry {
+            sender_name: "bob".to_string(),
+            sender_user_id: None,
+            message: message.to_string(),
+            time,
+            delivered: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn mailbox_cap_expiry_and_delivery() {
+        let store = MailStore::open(None).unwrap();
+        let max_age = chrono::Duration::days(1);
+        let now = Utc::now();
+
+        assert!(store.add(1, mail("old", now - chrono::Duration::days(2)), 2, max_age).await.unwrap());
+        assert!(store.add(1, mail("a", now), 2, max_age).await.unwrap());
+        assert!(store.add(1, mail("b", now), 2, max_age).await.unwrap());
+        assert!(!store.add(1, mail("c", now), 2, max_age).await.unwrap());
+
+        assert_eq!(store.take_undelivered(1, max_age).await.unwrap().len(), 2);
+        assert!(store.take_undelivered(1, max_age).await.unwrap().is_empty());
+        assert_eq!(store.get(1, max_age).await.len(), 2);
+
+        assert_eq!(store.clear(1).await.unwrap(), 2);
+        assert!(store.get(1, max_age).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn mailboxes_persist_per_user() {
+        let directory = std::env::temp_dir().join(format!("mail-store-{}", std::process::id()));
+        let path = directory.to_str().unwrap();
+        let max_age = chrono::Duration::days(1);
+
+        let store = MailStore::open(Some(path)).unwrap();
+        assert!(store.add(7, mail("hi", Utc::now()), 2, max_age).await.unwrap());
+        assert!(directory.join("7.json").exists());
+
+        let reopened = MailStore::open(Some(path)).unwrap();
+        assert_eq!(reopened.get(7, max_age).await.len(), 1);
+        assert_eq!(reopened.clear(7).await.unwrap(), 1);
+        assert!(!directory.join("7.json").exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
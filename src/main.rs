@@ -1,20 +1,35 @@
 use crate::{config::Config, server::Server};
 
 mod acl;
+mod admission;
+mod afk;
+mod announcements;
+mod audit;
+mod autoban;
+mod bandwidth;
+mod bans;
+mod blob_store;
 mod channels;
+mod chat_history;
 mod client;
 mod client_repository;
 mod codec_info;
 mod config;
 mod constants;
 mod geoip;
+mod html_sanitizer;
+mod mailbox;
 mod messages;
 mod server;
+mod texture;
 mod types;
+mod user_registry;
+mod voice;
 mod voice_crypto;
 mod client_certificate_verifier;
 mod proxy_protocol;
 mod protocol_version;
+mod rate_limit;
 
 mod mumble_proto {
     include!(concat!(env!("OUT_DIR"), "/mumble_proto.rs"));
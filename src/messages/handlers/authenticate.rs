@@ -0,0 +1,239 @@
+use std::sync::Arc;
+
+use crate::{
+    acl::ACLPermissions,
+    autoban::AutobanEvent,
+    channels::ROOT_CHANNEL_ID,
+    codec_info::CELT_0_7_0_BITSTREAM,
+    client::{
+        client::Client,
+        states::ConnectionState,
+        user_info::{UserInfo, UserInfoExtended},
+    },
+    mailbox,
+    messages::Message,
+    mumble_proto::{reject::RejectType, Authenticate, Reject, ServerSync},
+    server::Server,
+    user_registry::normalize_name,
+};
+
+async fn reject(
+    client: &Client,
+    reject_type: RejectType,
+    reason: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut message = Reject {
+        r#type: None,
+        reason: Some(reason.to_string()),
+    };
+    message.set_type(reject_type);
+    client.write_proto_message(&Message::Reject(message)).await?;
+    client.set_connection_state(ConnectionState::Dead).await;
+    Err(format!("Client rejected: {}", reason).into())
+}
+
+pub async fn handle_authenticate(
+    server: &Server,
+    client: &Arc<Box<Client>>,
+    content: Authenticate,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if client.is_authenticated().await {
+        return Ok(());
+    }
+
+    let policy = server.get_connection_policy();
+    let session_id = client.get_session_id();
+    let username = content.username.as_deref().unwrap_or_default().trim().to_string();
+
+    if username.is_empty() {
+        tracing::info!(session_id, "Rejected: empty username");
+        return reject(client, RejectType::InvalidUsername, "Invalid username").await;
+    }
+
+    if policy.require_certificate && !client.has_certificate() {
+        tracing::info!(session_id, username, "Rejected: no client certificate");
+        return reject(
+            client,
+            RejectType::NoCertificate,
+            "A client certificate is required to connect to this server",
+        )
+        .await;
+    }
+
+    if policy.require_verified_certificate && !client.is_verified() {
+        tracing::info!(session_id, username, "Rejected: client certificate chain is not trusted");
+        return reject(
+            client,
+            RejectType::NoCertificate,
+            "Your certificate was not issued by an authority trusted by this server",
+        )
+        .await;
+    }
+
+    if let Some(hash) = client.get_certificate_hash() {
+        if let Some(ban) = server.get_bans().find_by_certificate_hash(hash).await {
+            tracing::info!(session_id, username, reason = ban.reason, "Rejected: certificate is banned");
+            let reason = match ban.reason.is_empty() {
+                true => "You are banned from this server".to_string(),
+                false => format!("You are banned from this server: {}", ban.reason),
+            };
+            return reject(client, RejectType::None, &reason).await;
+        }
+    }
+
+    let registry = server.get_registry();
+    let registered_user = match client.get_certificate_hash() {
+        Some(hash) => registry.get_by_certificate_hash(hash).await,
+        None => None,
+    };
+
+    if registered_user.is_none() && registry.get_by_name(&username).await.is_some() {
+        tracing::info!(session_id, username, "Rejected: name belongs to a registered user");
+        server
+            .record_autoban_event(client.get_real_ip_address(), AutobanEvent::FailedAuthentication)
+            .await;
+        return reject(
+            client,
+            RejectType::WrongUserPw,
+            "This name is registered with a different certificate",
+        )
+        .await;
+    }
+
+    // Registered users are identified by their certificate and bypass the server password
+    if registered_user.is_none() {
+        if let Some(server_password) = &policy.server_password {
+            let password = content.password.as_deref().unwrap_or_default();
+            if aws_lc_rs::constant_time::verify_slices_are_equal(
+                password.as_bytes(),
+                server_password.as_bytes(),
+            )
+            .is_err()
+            {
+                tracing::info!(session_id, username, "Rejected: wrong server password");
+                server
+                    .record_autoban_event(client.get_real_ip_address(), AutobanEvent::FailedAuthentication)
+                    .await;
+                return reject(client, RejectType::WrongServerPw, "Wrong server password").await;
+            }
+        }
+    }
+
+    if policy.registered_users_only && registered_user.is_none() {
+        tracing::info!(session_id, username, "Rejected: server only admits registered users");
+        return reject(
+            client,
+            RejectType::None,
+            "Only registered users may connect to this server",
+        )
+        .await;
+    }
+
+    let username = match &registered_user {
+        Some(user) => user.name.clone(),
+        None => username,
+    };
+
+    let normalized_name = normalize_name(&username);
+    for other in server.get_clients().get_clients().await {
+        if other.get_session_id() == session_id || !other.is_authenticated().await {
+            continue;
+        }
+
+        if other.get_username().await.is_some_and(|name| normalize_name(&name) == normalized_name) {
+            tracing::info!(session_id, username, "Rejected: username already in use");
+            return reject(client, RejectType::UsernameInUse, "Username already in use").await;
+        }
+    }
+
+    if let Some(max_users) = server.get_announcements().await.get_max_users() {
+        let mut users = 0;
+        for other in server.get_clients().get_clients().await {
+            if other.is_authenticated().await {
+                users += 1;
+            }
+        }
+        if users >= max_users {
+            tracing::info!(session_id, username, "Rejected: server is full");
+            return reject(client, RejectType::ServerFull, "Server is full").await;
+        }
+    }
+
+    let last_channel_id = registered_user.as_ref().and_then(|user| user.last_channel_id);
+    if let Some(user) = &registered_user {
+        let decode = |hash: &Option<String>| hash.as_deref().and_then(|hash| hex::decode(hash).ok());
+        client.set_comment_hash(decode(&user.comment_hash)).await;
+        client.set_texture_hash(decode(&user.texture_hash)).await;
+    }
+    client.set_user_id(registered_user.as_ref().map(|user| user.user_id)).await;
+    client
+        .set_user_info(UserInfo::new(
+            registered_user.map(|user| user.groups).unwrap_or_default(),
+            content.tokens.into_iter().collect(),
+            None,
+        ))
+        .await;
+    client
+        .set_user_info_extended(UserInfoExtended::new(username.clone(), content.password))
+        .await;
+    let celt_versions = match (content.celt_versions.is_empty(), content.opus.unwrap_or(false)) {
+        (true, false) => vec![CELT_0_7_0_BITSTREAM],
+        _ => content.celt_versions,
+    };
+    client.set_codecs(celt_versions, content.opus.unwrap_or(false)).await;
+    client.set_connection_state(ConnectionState::Authenticated).await;
+
+    if let Some(channel_id) = last_channel_id {
+        let exists = server.get_channels().await.get_channel(channel_id).is_some();
+        if exists && server.has_permission(client, channel_id, ACLPermissions::Enter).await {
+            client.set_current_channel_id(channel_id).await;
+        }
+    }
+
+    tracing::info!(session_id, username, "Authenticated");
+
+    // A changed vote has already been broadcast to everyone including this client
+    if !server.recheck_codecs(Some(client)).await {
+        client
+            .write_proto_message(&Message::CodecVersion(server.get_codec_version().await))
+            .await?;
+    }
+
+    for message in server.get_sync_messages().await {
+        client.write_proto_message(&message).await?;
+    }
+
+    let user_state = Message::UserState(client.to_user_state().await);
+    for other in server.get_clients().get_clients().await {
+        if other.get_session_id() != session_id && other.is_authenticated().await {
+            if let Err(e) = other.write_proto_message(&user_state).await {
+                tracing::debug!(session_id, other = other.get_session_id(), "Failed to announce user: {}", e);
+            }
+        }
+    }
+
+    let permissions = server.get_permissions(client, ROOT_CHANNEL_ID).await;
+    let (server_sync, server_config, suggest_config) = {
+        let announcements = server.get_announcements().await;
+        let server_sync = ServerSync {
+            session: Some(session_id),
+            max_bandwidth: Some(announcements.get_max_bandwidth()),
+            welcome_text: announcements.get_welcome_text().map(str::to_string),
+            permissions: Some(permissions.bits() as u64),
+        };
+        (server_sync, announcements.get_sync_config(), announcements.get_suggest_config())
+    };
+    client.write_proto_message(&Message::ServerSync(server_sync)).await?;
+    client.write_proto_message(&Message::ServerConfig(server_config)).await?;
+    if let Some(suggest_config) = suggest_config {
+        client.write_proto_message(&Message::SuggestConfig(suggest_config)).await?;
+    }
+    client.set_connection_state(ConnectionState::Ready).await;
+
+    server
+        .replay_chat_history(client, client.get_current_channel_id().await)
+        .await?;
+    mailbox::deliver_mail(server, client).await?;
+
+    Ok(())
+}
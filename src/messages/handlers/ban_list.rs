@@ -1,5 +1,56 @@
-use crate::mumble_proto::BanList;
+use std::sync::Arc;
 
-pub fn handle_ban_list(content: BanList) {
-    // Handle ban list message logic here
+use chrono::Utc;
+
+use crate::{
+    acl::ACLPermissions,
+    bans::Ban,
+    channels::ROOT_CHANNEL_ID,
+    client::client::Client,
+    messages::Message,
+    mumble_proto::BanList,
+    server::Server,
+};
+
+pub async fn handle_ban_list(
+    server: &Server,
+    client: &Arc<Box<Client>>,
+    content: BanList,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !server
+        .has_permission(client, ROOT_CHANNEL_ID, ACLPermissions::Ban)
+        .await
+    {
+        return client
+            .send_permission_denied(ROOT_CHANNEL_ID, ACLPermissions::Ban)
+            .await;
+    }
+
+    let bans = server.get_bans();
+
+    if content.query.unwrap_or(false) {
+        let entries = bans.get_bans().await.iter().map(Ban::to_entry).collect();
+        return client
+            .write_proto_message(&Message::BanList(BanList {
+                bans: entries,
+                query: None,
+            }))
+            .await;
+    }
+
+    let now = Utc::now();
+    let mut new_bans = Vec::with_capacity(content.bans.len());
+    for entry in &content.bans {
+        match Ban::from_entry(entry, now) {
+            Ok(ban) => new_bans.push(ban),
+            Err(e) => tracing::warn!(session_id = client.get_session_id(), "Ignored invalid ban entry: {}", e),
+        }
+    }
+
+    tracing::info!(
+        session_id = client.get_session_id(),
+        count = new_bans.len(),
+        "Ban list updated"
+    );
+    bans.replace(new_bans).await
 }
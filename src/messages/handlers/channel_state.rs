@@ -1,6 +1,36 @@
-use crate::mumble_proto::ChannelState;
+use std::sync::Arc;
 
-pub fn handle_channel_state(content: ChannelState) {
-    // Handle channel state message logic here
+use crate::{acl::ACLPermissions, client::client::Client, mumble_proto::ChannelState, server::Server};
+
+/// Only description changes of existing channels are supported
+pub async fn handle_channel_state(
+    server: &Server,
+    client: &Arc<Box<Client>>,
+    content: ChannelState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !client.is_authenticated().await {
+        return Ok(());
+    }
+
+    let (Some(channel_id), Some(description)) = (content.channel_id, content.description) else {
+        return Ok(());
+    };
+    if server.get_channels().await.get_channel(channel_id).is_none() {
+        return Ok(());
+    }
+    if !server.has_permission(client, channel_id, ACLPermissions::Write).await {
+        return client.send_permission_denied(channel_id, ACLPermissions::Write).await;
+    }
+
+    let rejection = match server.set_channel_description(channel_id, &description).await {
+        Ok(()) => None,
+        Err(e) => Some(format!("Description rejected: {}", e)),
+    };
+    match rejection {
+        Some(reason) => client.send_text_denied(&reason).await,
+        None => {
+            tracing::info!(session_id = client.get_session_id(), channel_id, "Changed channel description");
+            Ok(())
+        }
+    }
 }
-    
\ No newline at end of file
@@ -1,13 +1,16 @@
 mod acl;
+mod authenticate;
 mod ban_list;
 mod channel_remove;
 mod channel_state;
 mod crypt_setup;
 mod permission_query;
 mod ping;
+mod plugin_data_transmission;
 mod query_users;
 mod request_blob;
 mod text_message;
+mod udp_tunnel;
 mod user_list;
 mod user_remove;
 mod user_state;
@@ -15,17 +18,20 @@ mod user_stats;
 mod voice_target;
 
 pub(crate) use acl::handle_acl;
+pub(crate) use authenticate::handle_authenticate;
 pub(crate) use ban_list::handle_ban_list;
 pub(crate) use channel_remove::handle_channel_remove;
 pub(crate) use channel_state::handle_channel_state;
 pub(crate) use crypt_setup::handle_crypt_setup;
 pub(crate) use permission_query::handle_permission_query;
 pub(crate) use ping::handle_ping;
+pub(crate) use plugin_data_transmission::handle_plugin_data_transmission;
 pub(crate) use query_users::handle_query_users;
-// pub use request_blob::handle_request_blob;
-// pub use text_message::handle_text_message;
+pub(crate) use request_blob::handle_request_blob;
+pub(crate) use text_message::handle_text_message;
+pub(crate) use udp_tunnel::handle_udp_tunnel;
+pub(crate) use user_remove::handle_user_remove;
+pub(crate) use user_state::handle_user_state;
+pub(crate) use user_stats::handle_user_stats;
 // pub use user_list::handle_user_list;
-// pub use user_remove::handle_user_remove;
-// pub use user_state::handle_user_state;
-// pub use user_stats::handle_user_stats;
 // pub use voice_target::handle_voice_target;
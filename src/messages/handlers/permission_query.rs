@@ -1,5 +1,29 @@
-use crate::mumble_proto::PermissionQuery;
+use std::sync::Arc;
 
-pub fn handle_permission_query(content: PermissionQuery) {
-    // Handle permission query message logic here
+use crate::{client::client::Client, messages::Message, mumble_proto::PermissionQuery, server::Server};
+
+pub async fn handle_permission_query(
+    server: &Server,
+    client: &Arc<Box<Client>>,
+    content: PermissionQuery,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !client.is_authenticated().await {
+        return Ok(());
+    }
+
+    let Some(channel_id) = content.channel_id else {
+        return Ok(());
+    };
+    if server.get_channels().await.get_channel(channel_id).is_none() {
+        return Ok(());
+    }
+
+    let permissions = server.get_permissions(client, channel_id).await;
+    client
+        .write_proto_message(&Message::PermissionQuery(PermissionQuery {
+            channel_id: Some(channel_id),
+            permissions: Some(permissions.bits()),
+            flush: None,
+        }))
+        .await
 }
@@ -1,5 +1,23 @@
-use crate::mumble_proto::Ping;
+use std::sync::Arc;
 
-pub fn handle_ping(content: Ping) {
-    // Handle ping message logic here
+use crate::{client::client::Client, messages::Message, mumble_proto::Ping, server::Server};
+
+pub async fn handle_ping(
+    _server: &Server,
+    client: &Arc<Box<Client>>,
+    content: Ping,
+) -> Result<(), Box<dyn std::error::Error>> {
+    client.record_ping(&content).await;
+
+    let counters = client.get_local_crypto_counters().await;
+    client
+        .write_proto_message(&Message::Ping(Ping {
+            timestamp: content.timestamp,
+            good: Some(counters.good),
+            late: Some(counters.late),
+            lost: Some(counters.lost),
+            resync: Some(counters.resync),
+            ..Default::default()
+        }))
+        .await
 }
@@ -0,0 +1,69 @@
+use std::sync::Arc;
+
+use crate::{
+    acl::ACLPermissions,
+    client::{client::Client, client_session_identifier::ClientSessionIdentifier},
+    messages::Message,
+    mumble_proto::PluginDataTransmission,
+    server::Server,
+};
+
+pub async fn handle_plugin_data_transmission(
+    server: &Server,
+    client: &Arc<Box<Client>>,
+    mut content: PluginDataTransmission,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = server.get_plugin_data_config();
+    if !config.enabled || !client.is_authenticated().await {
+        return Ok(());
+    }
+
+    let session_id = client.get_session_id();
+    let payload_len = content.data.as_ref().map_or(0, Vec::len);
+    if payload_len > config.max_payload_bytes {
+        tracing::debug!(session_id, payload_len, "Dropped oversized plugin data");
+        return Ok(());
+    }
+
+    if !server.check_plugin_data_rate(session_id).await {
+        tracing::debug!(session_id, "Dropped plugin data over the rate limit");
+        return Ok(());
+    }
+
+    let receivers = std::mem::take(&mut content.receiver_sessions);
+    content.sender_session = Some(session_id);
+    let message = Message::PluginDataTransmission(content);
+
+    let sender_channel_id = client.get_current_channel_id().await;
+    for receiver_id in receivers {
+        if receiver_id == session_id {
+            continue;
+        }
+
+        let Ok(identifier) = ClientSessionIdentifier::try_from(receiver_id) else {
+            continue;
+        };
+        let Some(receiver) = server.get_clients().get_client(identifier).await else {
+            continue;
+        };
+        if !receiver.is_authenticated().await {
+            continue;
+        }
+
+        // Outside the sender's channel, the sender needs TextMessage in the receiver's channel
+        let receiver_channel_id = receiver.get_current_channel_id().await;
+        if receiver_channel_id != sender_channel_id
+            && !server
+                .has_permission(client, receiver_channel_id, ACLPermissions::TextMessage)
+                .await
+        {
+            continue;
+        }
+
+        if let Err(e) = receiver.write_proto_message(&message).await {
+            tracing::debug!(session_id, receiver_id, "Failed to relay plugin data: {}", e);
+        }
+    }
+
+    Ok(())
+}
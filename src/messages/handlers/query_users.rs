@@ -1,5 +1,24 @@
-use crate::mumble_proto::QueryUsers;
+use std::sync::Arc;
 
-pub fn handle_query_users(content: QueryUsers) {
-    // Handle query users message logic here
+use crate::{client::client::Client, messages::Message, mumble_proto::QueryUsers, server::Server};
+
+pub async fn handle_query_users(
+    server: &Server,
+    client: &Arc<Box<Client>>,
+    content: QueryUsers,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !client.is_authenticated().await {
+        return Ok(());
+    }
+
+    let (ids, names) = server
+        .get_registry()
+        .resolve(&content.ids, &content.names)
+        .await
+        .into_iter()
+        .unzip();
+
+    client
+        .write_proto_message(&Message::QueryUsers(QueryUsers { ids, names }))
+        .await
 }
@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use crate::{
+    client::{client::Client, client_session_identifier::ClientSessionIdentifier},
+    messages::Message,
+    mumble_proto::{ChannelState, RequestBlob, UserState},
+    server::Server,
+};
+
+async fn find_client(server: &Server, session_id: u32) -> Option<Arc<Box<Client>>> {
+    let identifier = ClientSessionIdentifier::try_from(session_id).ok()?;
+    let client = server.get_clients().get_client(identifier).await?;
+    match client.is_authenticated().await {
+        true => Some(client),
+        false => None,
+    }
+}
+
+pub async fn handle_request_blob(
+    server: &Server,
+    client: &Arc<Box<Client>>,
+    content: RequestBlob,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !client.is_authenticated().await {
+        return Ok(());
+    }
+
+    let blobs = server.get_blobs();
+
+    for session_id in content.session_texture {
+        let Some(target) = find_client(server, session_id).await else {
+            continue;
+        };
+        let Some(hash) = target.get_texture_hash().await else {
+            continue;
+        };
+        if let Some(texture) = blobs.get(&hash).await {
+            client
+                .write_proto_message(&Message::UserState(UserState {
+                    session: Some(session_id),
+                    texture: Some(texture.to_vec()),
+                    ..Default::default()
+                }))
+                .await?;
+        }
+    }
+
+    for session_id in content.session_comment {
+        let Some(target) = find_client(server, session_id).await else {
+            continue;
+        };
+        let Some(hash) = target.get_comment_hash().await else {
+            continue;
+        };
+        if let Some(comment) = blobs.get(&hash).await {
+            client
+                .write_proto_message(&Message::UserState(UserState {
+                    session: Some(session_id),
+                    comment: Some(String::from_utf8_lossy(&comment).into_owned()),
+                    ..Default::default()
+                }))
+                .await?;
+        }
+    }
+
+    for channel_id in content.channel_description {
+        let hash = match server.get_channels().await.get_channel(channel_id) {
+            Some(channel) => channel.get_description_hash().map(<[u8]>::to_vec),
+            None => None,
+        };
+        let Some(hash) = hash else {
+            continue;
+        };
+        if let Some(description) = blobs.get(&hash).await {
+            client
+                .write_proto_message(&Message::ChannelState(ChannelState {
+                    channel_id: Some(channel_id),
+                    description: Some(String::from_utf8_lossy(&description).into_owned()),
+                    ..Default::default()
+                }))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{
+    acl::ACLPermissions,
+    client::{client::Client, client_session_identifier::ClientSessionIdentifier},
+    html_sanitizer::ContentKind,
+    mailbox,
+    messages::Message,
+    mumble_proto::{permission_denied::DenyType, TextMessage},
+    server::Server,
+};
+
+pub async fn handle_text_message(
+    server: &Server,
+    client: &Arc<Box<Client>>,
+    mut content: TextMessage,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !client.is_authenticated().await {
+        return Ok(());
+    }
+
+    let session_id = client.get_session_id();
+    let sanitized = {
+        let announcements = server.get_announcements().await;
+        announcements
+            .get_html_sanitizer()
+            .sanitize(ContentKind::TextMessage, &content.message)
+            .filter(|message| announcements.get_text_message_config().allows_length(message))
+    };
+    let Some(sanitized) = sanitized else {
+        return client.send_denied(DenyType::TextTooLong).await;
+    };
+    content.message = sanitized;
+
+    if mailbox::is_mail_command(&content.message) {
+        return mailbox::handle_mail_command(server, client, &content.message).await;
+    }
+
+    let mut target_channels = HashSet::new();
+
+    for &channel_id in &content.channel_id {
+        if server.get_channels().await.get_channel(channel_id).is_none() {
+            continue;
+        }
+        if !server.has_permission(client, channel_id, ACLPermissions::TextMessage).await {
+            return client.send_permission_denied(channel_id, ACLPermissions::TextMessage).await;
+        }
+        target_channels.insert(channel_id);
+    }
+
+    // Channels in a tree the sender may not write to are skipped silently
+    for &tree_id in &content.tree_id {
+        let tree_ids = server.get_channels().await.get_tree_ids(tree_id);
+        for channel_id in tree_ids {
+            if server.has_permission(client, channel_id, ACLPermissions::TextMessage).await {
+                target_channels.insert(channel_id);
+            }
+        }
+    }
+
+    let mut recipients = Vec::new();
+
+    for &target_id in &content.session {
+        let Ok(identifier) = ClientSessionIdentifier::try_from(target_id) else {
+            continue;
+        };
+        let Some(target) = server.get_clients().get_client(identifier).await else {
+            continue;
+        };
+        if !target.is_authenticated().await {
+            continue;
+        }
+
+        let channel_id = target.get_current_channel_id().await;
+        if !server.has_permission(client, channel_id, ACLPermissions::TextMessage).await {
+            return client.send_permission_denied(channel_id, ACLPermissions::TextMessage).await;
+        }
+        recipients.push(target);
+    }
+
+    if !target_channels.is_empty() {
+        for other in server.get_clients().get_clients().await {
+            if other.is_authenticated().await
+                && target_channels.contains(&other.get_current_channel_id().await)
+            {
+                recipients.push(other);
+            }
+        }
+    }
+
+    if !target_channels.is_empty() {
+        let channel_ids = target_channels.iter().copied().collect::<Vec<_>>();
+        let sender_name = client.get_username().await.unwrap_or_default();
+        server.record_chat_history(&channel_ids, sender_name, &content.message).await;
+    }
+
+    content.actor = Some(session_id);
+    let message = Message::TextMessage(content);
+
+    let mut delivered = HashSet::new();
+    for recipient in recipients {
+        let recipient_id = recipient.get_session_id();
+        if recipient_id == session_id || !delivered.insert(recipient_id) {
+            continue;
+        }
+
+        if let Err(e) = recipient.write_proto_message(&message).await {
+            tracing::debug!(session_id, recipient_id, "Failed to deliver text message: {}", e);
+        }
+    }
+
+    Ok(())
+}
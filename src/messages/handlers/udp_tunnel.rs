@@ -0,0 +1,16 @@
+use std::sync::Arc;
+
+use crate::{client::client::Client, server::Server};
+
+pub async fn handle_udp_tunnel(
+    server: &Server,
+    client: &Arc<Box<Client>>,
+    packet: Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !client.is_authenticated().await {
+        return Ok(());
+    }
+
+    server.relay_voice(client, &packet).await;
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use std::{net::IpAddr, sync::Arc};
+
+use chrono::Utc;
+
+use crate::{
+    acl::{ACLPermissions, SUPERUSER_ID},
+    audit::{AuditAction, AuditEntry},
+    bans::Ban,
+    channels::ROOT_CHANNEL_ID,
+    client::{client::Client, client_session_identifier::ClientSessionIdentifier},
+    mumble_proto::{permission_denied::DenyType, UserRemove},
+    server::Server,
+};
+
+pub async fn handle_user_remove(
+    server: &Server,
+    client: &Arc<Box<Client>>,
+    content: UserRemove,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !client.is_authenticated().await {
+        return Ok(());
+    }
+
+    let Ok(identifier) = ClientSessionIdentifier::try_from(content.session) else {
+        return Ok(());
+    };
+    let target = match server.get_clients().get_client(identifier).await {
+        Some(target) if target.is_authenticated().await => target,
+        _ => return Ok(()),
+    };
+
+    let ban = content.ban.unwrap_or(false);
+    let permission = match ban {
+        true => ACLPermissions::Ban,
+        false => ACLPermissions::Kick,
+    };
+    if !server.has_permission(client, ROOT_CHANNEL_ID, permission).await {
+        return client.send_permission_denied(ROOT_CHANNEL_ID, permission).await;
+    }
+
+    let target_user_id = target.get_user_id().await;
+    if target_user_id == Some(SUPERUSER_ID) {
+        return client.send_denied(DenyType::SuperUser).await;
+    }
+
+    let reason = content.reason.unwrap_or_default();
+    let target_name = target.get_username().await;
+    let target_address = target.get_real_ip_address().to_canonical();
+    let target_certificate_hash = target.get_certificate_hash().map(hex::encode);
+
+    if ban {
+        let mask = match target_address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        server
+            .get_bans()
+            .add(Ban {
+                address: target_address,
+                mask,
+                name: target_name.clone().unwrap_or_default(),
+                certificate_hash: target_certificate_hash.clone(),
+                reason: reason.clone(),
+                start: Utc::now(),
+                duration: 0,
+            })
+            .await?;
+    }
+
+    let entry = AuditEntry {
+        time: Utc::now(),
+        action: match ban {
+            true => AuditAction::Ban,
+            false => AuditAction::Kick,
+        },
+        actor_session: client.get_session_id(),
+        actor_name: client.get_username().await,
+        actor_user_id: client.get_user_id().await,
+        target_session: target.get_session_id(),
+        target_name,
+        target_user_id,
+        target_address,
+        target_certificate_hash,
+        reason: reason.clone(),
+    };
+    if let Err(e) = server.get_audit_log().record(&entry).await {
+        tracing::error!("Failed to write audit log: {}", e);
+    }
+
+    target
+        .disconnect_with(UserRemove {
+            session: target.get_session_id(),
+            actor: Some(client.get_session_id()),
+            reason: Some(reason),
+            ban: Some(ban),
+        })
+        .await;
+
+    Ok(())
+}
@@ -0,0 +1,240 @@
+use std::sync::Arc;
+
+use crate::{
+    acl::ACLPermissions,
+    channels::ROOT_CHANNEL_ID,
+    client::{client::Client, client_session_identifier::ClientSessionIdentifier},
+    html_sanitizer::ContentKind,
+    messages::Message,
+    mumble_proto::{permission_denied::DenyType, UserState},
+    server::Server,
+};
+
+pub async fn handle_user_state(
+    server: &Server,
+    client: &Arc<Box<Client>>,
+    mut content: UserState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !client.is_authenticated().await {
+        return Ok(());
+    }
+
+    let actor_id = client.get_session_id();
+    let target = match content.session {
+        Some(session_id) if session_id != actor_id => {
+            let Ok(identifier) = ClientSessionIdentifier::try_from(session_id) else {
+                return Ok(());
+            };
+            match server.get_clients().get_client(identifier).await {
+                Some(target) if target.is_authenticated().await => target,
+                _ => return Ok(()),
+            }
+        }
+        _ => Arc::clone(client),
+    };
+    let target_id = target.get_session_id();
+    let is_self = target_id == actor_id;
+    let current_channel_id = target.get_current_channel_id().await;
+
+    // Names and registration cannot be changed through UserState
+    content.name = None;
+    content.user_id = None;
+    content.listening_volume_adjustment.clear();
+
+    // Self state can only be changed by the user themselves
+    if !is_self
+        && (content.self_mute.is_some()
+            || content.self_deaf.is_some()
+            || content.recording.is_some()
+            || content.texture.as_ref().is_some_and(|t| !t.is_empty())
+            || content.plugin_context.is_some()
+            || content.plugin_identity.is_some()
+            || !content.temporary_access_tokens.is_empty()
+            || !content.listening_channel_add.is_empty()
+            || !content.listening_channel_remove.is_empty())
+    {
+        return Ok(());
+    }
+
+    if content.recording == Some(true) && !server.get_announcements().await.is_recording_allowed() {
+        return client.send_text_denied("Recording is not allowed on this server").await;
+    }
+
+    // Others may only have their comment or texture cleared
+    if !is_self && (content.comment.is_some() || content.texture.is_some()) {
+        if content.comment.as_ref().is_some_and(|c| !c.is_empty()) {
+            return Ok(());
+        }
+        if !server.has_permission(client, ROOT_CHANNEL_ID, ACLPermissions::ResetUserContent).await {
+            return client.send_permission_denied(ROOT_CHANNEL_ID, ACLPermissions::ResetUserContent).await;
+        }
+    }
+
+    if content.mute.is_some()
+        || content.deaf.is_some()
+        || content.suppress.is_some()
+        || content.priority_speaker.is_some()
+    {
+        if !server.has_permission(client, current_channel_id, ACLPermissions::MuteDeafen).await {
+            return client.send_permission_denied(current_channel_id, ACLPermissions::MuteDeafen).await;
+        }
+    }
+
+    let move_to = match content.channel_id {
+        Some(channel_id) if channel_id != current_channel_id => {
+            if server.get_channels().await.get_channel(channel_id).is_none() {
+                return Ok(());
+            }
+
+            if !is_self && !server.has_permission(client, current_channel_id, ACLPermissions::Move).await {
+                return client.send_permission_denied(current_channel_id, ACLPermissions::Move).await;
+            }
+            if !server.has_permission(client, channel_id, ACLPermissions::Move).await
+                && !server.has_permission(&target, channel_id, ACLPermissions::Enter).await
+            {
+                return client.send_permission_denied(channel_id, ACLPermissions::Enter).await;
+            }
+            if server.is_channel_full(channel_id).await
+                && !server.has_permission(client, channel_id, ACLPermissions::Write).await
+            {
+                return client.send_denied(DenyType::ChannelFull).await;
+            }
+            Some(channel_id)
+        }
+        _ => None,
+    };
+    content.channel_id = move_to;
+
+    let mut listening_channel_add = Vec::new();
+    for channel_id in std::mem::take(&mut content.listening_channel_add) {
+        if server.get_channels().await.get_channel(channel_id).is_none() {
+            continue;
+        }
+        if !server.has_permission(client, channel_id, ACLPermissions::Listen).await {
+            return client.send_permission_denied(channel_id, ACLPermissions::Listen).await;
+        }
+        listening_channel_add.push(channel_id);
+    }
+    content.listening_channel_add = listening_channel_add;
+
+    let comment = match content.comment.take() {
+        Some(comment) => {
+            let sanitized = {
+                let announcements = server.get_announcements().await;
+                announcements
+                    .get_html_sanitizer()
+                    .sanitize(ContentKind::UserComment, &comment)
+                    .filter(|comment| announcements.get_text_message_config().allows_length(comment))
+            };
+            let Some(comment) = sanitized else {
+                return client.send_denied(DenyType::TextTooLong).await;
+            };
+            Some(comment)
+        }
+        None => None,
+    };
+
+    // All checks passed, apply the changes
+
+    if let Some(texture) = content.texture.take() {
+        let rejection = match server.set_user_texture(&target, actor_id, texture).await {
+            Ok(()) => None,
+            Err(e) => Some(format!("Texture rejected: {}", e)),
+        };
+        if let Some(reason) = rejection {
+            client.send_text_denied(&reason).await?;
+        }
+    }
+
+    if let Some(comment) = comment {
+        let hash = match comment.is_empty() {
+            true => None,
+            false => Some(server.get_blobs().put(comment.into_bytes()).await?),
+        };
+        // Clients fetch the comment itself through RequestBlob
+        content.comment = hash.is_none().then(String::new);
+        content.comment_hash = hash.clone();
+        if let Some(user_id) = target.get_user_id().await {
+            if let Err(e) = server.get_registry().set_comment_hash(user_id, hash.as_deref()).await {
+                tracing::error!(session_id = target_id, user_id, "Failed to persist comment: {}", e);
+            }
+        }
+        target.set_comment_hash(hash).await;
+    }
+
+    if !content.temporary_access_tokens.is_empty() {
+        let tokens = std::mem::take(&mut content.temporary_access_tokens);
+        client.set_tokens(tokens.into_iter().collect()).await;
+    }
+
+    {
+        let mut states = target.get_session_states_mut().await;
+        if let Some(plugin_context) = content.plugin_context.take() {
+            states.set_plugin_context(plugin_context);
+        }
+        if let Some(plugin_identity) = content.plugin_identity.take() {
+            states.set_plugin_identity(plugin_identity);
+        }
+
+        if let Some(self_deaf) = content.self_deaf {
+            states.set_self_deaf(self_deaf);
+        }
+        if let Some(self_mute) = content.self_mute {
+            states.set_self_mute(self_mute);
+        }
+        if content.self_mute.is_some() || content.self_deaf.is_some() {
+            content.self_mute = Some(states.is_self_mute());
+            content.self_deaf = Some(states.is_self_deaf());
+        }
+
+        if let Some(deaf) = content.deaf {
+            states.set_deaf(deaf);
+        }
+        if let Some(mute) = content.mute {
+            states.set_mute(mute);
+        }
+        if content.mute.is_some() || content.deaf.is_some() {
+            content.mute = Some(states.is_mute());
+            content.deaf = Some(states.is_deaf());
+        }
+
+        if let Some(suppress) = content.suppress {
+            states.set_suppress(suppress);
+        }
+        if let Some(priority_speaker) = content.priority_speaker {
+            states.set_priority_speaker(priority_speaker);
+        }
+        if let Some(recording) = content.recording {
+            states.set_recording(recording);
+        }
+    }
+
+    content.listening_channel_add.retain(|id| *id != move_to.unwrap_or(current_channel_id));
+    for &channel_id in &content.listening_channel_add {
+        target.listen_channel(channel_id).await;
+    }
+    for &channel_id in &content.listening_channel_remove {
+        target.unlisten_channel(channel_id).await;
+    }
+
+    let has_changes = content.mute.is_some()
+        || content.suppress.is_some()
+        || content.self_mute.is_some()
+        || content.priority_speaker.is_some()
+        || content.recording.is_some()
+        || content.comment.is_some()
+        || content.comment_hash.is_some()
+        || !content.listening_channel_add.is_empty()
+        || !content.listening_channel_remove.is_empty();
+    match move_to {
+        Some(channel_id) => server.move_user(&target, channel_id, actor_id, content).await?,
+        None if has_changes => {
+            content.session = Some(target_id);
+            content.actor = Some(actor_id);
+            server.broadcast(&Message::UserState(content)).await;
+        }
+        None => {}
+    }
+
+    Ok(())
+}
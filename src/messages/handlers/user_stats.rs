@@ -0,0 +1,127 @@
+use std::{net::IpAddr, sync::Arc};
+
+use crate::{
+    acl::ACLPermissions,
+    channels::ROOT_CHANNEL_ID,
+    client::{client::Client, client_session_identifier::ClientSessionIdentifier},
+    geoip,
+    messages::Message,
+    mumble_proto::{user_stats::Stats, UserStats},
+    server::Server,
+    voice_crypto::CryptoCounters,
+};
+
+fn to_stats(counters: CryptoCounters) -> Stats {
+    Stats {
+        good: Some(counters.good),
+        late: Some(counters.late),
+        lost: Some(counters.lost),
+        resync: Some(counters.resync),
+    }
+}
+
+/// Addresses are reported in the 16 byte IPv6 form, IPv4 as mapped addresses
+fn address_bytes(address: IpAddr) -> Vec<u8> {
+    match address {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped().octets().to_vec(),
+        IpAddr::V6(v6) => v6.octets().to_vec(),
+    }
+}
+
+fn describe_location(geoip: &geoip::Config) -> Option<String> {
+    let country = match (&geoip.country, &geoip.country_code) {
+        (Some(country), Some(code)) => Some(format!("{} ({})", country, code)),
+        (None, Some(code)) => Some(code.clone()),
+        (country, None) => country.clone(),
+    };
+    let network = geoip.asn.map(|asn| match &geoip.organization {
+        Some(organization) => format!("AS{} {}", asn, organization),
+        None => format!("AS{}", asn),
+    });
+
+    match (country, network) {
+        (Some(country), Some(network)) => Some(format!("{}, {}", country, network)),
+        (location, None) | (None, location) => location,
+    }
+}
+
+pub async fn handle_user_stats(
+    server: &Server,
+    client: &Arc<Box<Client>>,
+    content: UserStats,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !client.is_authenticated().await {
+        return Ok(());
+    }
+
+    let target = match content.session {
+        Some(session) => {
+            let Ok(identifier) = ClientSessionIdentifier::try_from(session) else {
+                return Ok(());
+            };
+            match server.get_clients().get_client(identifier).await {
+                Some(target) if target.is_authenticated().await => target,
+                _ => return Ok(()),
+            }
+        }
+        None => Arc::clone(client),
+    };
+
+    let is_self = target.get_session_id() == client.get_session_id();
+    let target_channel_id = target.get_current_channel_id().await;
+    // Users in other channels are only visible to those who could join them
+    if !is_self
+        && target_channel_id != client.get_current_channel_id().await
+        && !server.has_permission(client, target_channel_id, ACLPermissions::Enter).await
+    {
+        return client.send_permission_denied(target_channel_id, ACLPermissions::Enter).await;
+    }
+
+    let details = is_self
+        || server.has_permission(client, ROOT_CHANNEL_ID, ACLPermissions::Ban).await
+        || server.has_permission(client, ROOT_CHANNEL_ID, ACLPermissions::Kick).await;
+    let full = !content.stats_only.unwrap_or(false);
+
+    let stats = target.get_stats().await;
+    let mut message = UserStats {
+        session: Some(target.get_session_id()),
+        stats_only: Some(content.stats_only.unwrap_or(false)),
+        from_client: Some(to_stats(target.get_local_crypto_counters().await)),
+        from_server: Some(to_stats(stats.get_remote_counters())),
+        udp_packets: Some(stats.get_udp_packets()),
+        tcp_packets: Some(stats.get_tcp_packets()),
+        udp_ping_avg: Some(stats.get_udp_ping_avg()),
+        udp_ping_var: Some(stats.get_udp_ping_var()),
+        tcp_ping_avg: Some(stats.get_tcp_ping_avg()),
+        tcp_ping_var: Some(stats.get_tcp_ping_var()),
+        onlinesecs: Some(target.get_online_time().num_seconds().max(0) as u32),
+        idlesecs: Some(target.get_idle_time().await.as_secs() as u32),
+        bandwidth: Some(target.get_voice_bandwidth(server.get_voice_bandwidth_config()).await),
+        ..Default::default()
+    };
+
+    if full {
+        let (celt_versions, opus) = target.get_codecs().await;
+        message.celt_versions = celt_versions;
+        message.opus = Some(opus);
+
+        if details {
+            message.version = target.get_version_message().await;
+            // UserStats has no location fields; clients show the OS version next to the OS
+            if !is_self {
+                if let Some(location) = describe_location(target.get_geoip()) {
+                    let version = message.version.get_or_insert_with(Default::default);
+                    version.os_version = Some(match version.os_version.take() {
+                        Some(os_version) => format!("{}, location: {}", os_version, location),
+                        None => format!("location: {}", location),
+                    });
+                }
+            }
+            message.certificates = target.get_certificate_chain().to_vec();
+            message.strong_certificate = Some(target.is_verified());
+            message.address = Some(address_bytes(target.get_real_ip_address()));
+        }
+    }
+
+    client.write_proto_message(&Message::UserStats(message)).await
+}
@@ -34,11 +34,17 @@ pub fn message_conversion(input: TokenStream) -> TokenStream {
     let mut encoded_len_arms = Vec::new();
     let mut to_proto_arms = Vec::new();
     let mut to_proto_vec_arms = Vec::new();
+    let mut tag_consts = Vec::new();
 
     for (idx, variant) in data.variants.iter().enumerate() {
         let var_ident = &variant.ident;
         let discr = idx as u16;
 
+        let tag_ident = format_ident!("{}_TAG", var_ident.to_string().to_uppercase());
+        tag_consts.push(quote! {
+            pub const #tag_ident: u16 = #discr;
+        });
+
         match &variant.fields {
             Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
                 let ty = &fields.unnamed.first().unwrap().ty;
@@ -97,6 +103,8 @@ pub fn message_conversion(input: TokenStream) -> TokenStream {
 
     let expanded = quote! {
         impl #name {
+            #(#tag_consts)*
+
             pub fn from_proto(message_type: u16, buffer: Vec<u8>) -> Result<#name, Box<dyn std::error::Error>> {
                 match message_type {
                     #(#from_arms)*
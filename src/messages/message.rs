@@ -33,4 +33,5 @@ pub enum Message {
     RequestBlob(RequestBlob),
     ServerConfig(ServerConfig),
     SuggestConfig(SuggestConfig),
+    PluginDataTransmission(PluginDataTransmission),
 }
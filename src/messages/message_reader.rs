@@ -1,20 +1,110 @@
+use crate::config::MessageSizeLimitsConfig;
 use crate::messages::Message;
+
+/// Hard cap regardless of configuration
+pub const MAX_MESSAGE_LENGTH: u32 = 8 * 1024 * 1024;
+
+/// Largest body accepted for a message type, decided from the header alone
+pub fn size_limit(limits: &MessageSizeLimitsConfig, message_type: u16, authenticated: bool) -> u32 {
+    let limit = match (message_type, authenticated) {
+        (Message::VERSION_TAG, _) => limits.version,
+        (Message::PING_TAG, _) => limits.ping,
+        (Message::AUTHENTICATE_TAG, false) => limits.authenticate,
+        (_, false) => limits.unauthenticated,
+        (Message::UDPTUNNEL_TAG, true) => limits.udp_tunnel,
+        (Message::TEXTMESSAGE_TAG, true) => limits.text_message,
+        (Message::USERSTATE_TAG, true) => limits.user_state.max(limits.texture),
+        (_, true) => limits.default,
+    };
+    limit.min(MAX_MESSAGE_LENGTH)
+}
+
 pub trait ReadMessageExt {
-    async fn read_proto_message(&mut self) -> Result<Message, Box<dyn std::error::Error>>;
+    async fn read_proto_message(
+        &mut self,
+        limits: &MessageSizeLimitsConfig,
+        authenticated: bool,
+    ) -> Result<Message, Box<dyn std::error::Error>>;
 }
 
 impl<T: tokio::io::AsyncReadExt + Unpin> ReadMessageExt for T {
-    async fn read_proto_message(&mut self) -> Result<Message, Box<dyn std::error::Error>> {
+    async fn read_proto_message(
+        &mut self,
+        limits: &MessageSizeLimitsConfig,
+        authenticated: bool,
+    ) -> Result<Message, Box<dyn std::error::Error>> {
         let message_type = self.read_u16().await?;
         let message_length = self.read_u32().await?;
 
-        if message_length > 8 * 1024 * 1024 {
-            return Err("Message length exceeds maximum allowed size".into());
+        let limit = size_limit(limits, message_type, authenticated);
+        if message_length > limit {
+            return Err(format!(
+                "Message of type {} exceeds maximum allowed size ({} > {})",
+                message_type, message_length, limit
+            )
+            .into());
         }
 
         let mut buffer = vec![0u8; message_length as usize];
         self.read_exact(&mut buffer).await?;
 
-        Message::from_proto(message_type, buffer)
+        let message = Message::from_proto(message_type, buffer)?;
+
+        // Only texture uploads may use the texture sized limit
+        if let Message::UserState(state) = &message {
+            if message_length > limits.user_state && state.texture.as_ref().is_none_or(|t| t.is_empty()) {
+                return Err(format!(
+                    "UserState without texture exceeds maximum allowed size ({} > {})",
+                    message_length, limits.user_state
+                )
+                .into());
+            }
+        }
+
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(message_type: u16, body: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&message_type.to_be_bytes());
+        frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    #[tokio::test]
+    async fn limits_depend_on_authentication() {
+        let limits = MessageSizeLimitsConfig::default();
+        let text = frame(Message::TEXTMESSAGE_TAG, &[0x2a, 0x00]);
+
+        assert!(text.as_slice().read_proto_message(&limits, true).await.is_ok());
+
+        let oversized = frame(Message::TEXTMESSAGE_TAG, &vec![0; limits.unauthenticated as usize + 1]);
+        assert!(oversized.as_slice().read_proto_message(&limits, false).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_before_reading_body() {
+        let limits = MessageSizeLimitsConfig::default();
+        // Header announces a huge ping without providing the body
+        let mut header = Vec::new();
+        header.extend_from_slice(&Message::PING_TAG.to_be_bytes());
+        header.extend_from_slice(&(limits.ping + 1).to_be_bytes());
+
+        let error = header.as_slice().read_proto_message(&limits, true).await.unwrap_err();
+        assert!(error.to_string().contains("exceeds maximum allowed size"));
+    }
+
+    #[test]
+    fn texture_limit_only_for_user_state() {
+        let limits = MessageSizeLimitsConfig::default();
+        assert_eq!(size_limit(&limits, Message::USERSTATE_TAG, true), limits.texture);
+        assert_eq!(size_limit(&limits, Message::USERSTATE_TAG, false), limits.unauthenticated);
+        assert_eq!(size_limit(&limits, Message::ACL_TAG, true), limits.default);
     }
 }
@@ -1,5 +1,5 @@
 mod message;
-mod handlers;
+pub(crate) mod handlers;
 mod message_reader;
 mod message_writer;
 
@@ -40,6 +40,24 @@ impl From<ProtocolVersion> for u64 {
     }
 }
 
+impl std::str::FromStr for ProtocolVersion {
+    type Err = String;
+
+    /// Parses `major.minor[.patch]`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .trim()
+            .split('.')
+            .map(|part| part.parse::<u64>().map_err(|_| format!("Invalid version: {}", s)))
+            .collect::<Result<Vec<_>, _>>()?;
+        match parts[..] {
+            [major, minor] => Ok(Self { major, minor, patch: 0 }),
+            [major, minor, patch] => Ok(Self { major, minor, patch }),
+            _ => Err(format!("Invalid version: {}", s)),
+        }
+    }
+}
+
 impl ToString for ProtocolVersion {
     fn to_string(&self) -> String {
         format!("{}.{}.{}", self.major, self.minor, self.patch)
@@ -0,0 +1,250 @@
+use std::{collections::HashMap, hash::Hash, net::IpAddr, time::Instant};
+
+use cidr::{IpCidr, IpInet};
+
+use crate::{
+    config::{MessageRateLimitConfig, RateLimit},
+    messages::Message,
+};
+
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    /// Tokens added per second
+    refill_rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: f64, refill_rate: f64) -> Self {
+        Self::new_at(capacity, refill_rate, Instant::now())
+    }
+
+    pub fn new_at(capacity: f64, refill_rate: f64, now: Instant) -> Self {
+        TokenBucket {
+            capacity,
+            refill_rate,
+            tokens: capacity,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    pub fn try_consume(&mut self, amount: f64) -> bool {
+        self.try_consume_at(amount, Instant::now())
+    }
+
+    pub fn try_consume_at(&mut self, amount: f64, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= amount {
+            self.tokens -= amount;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_full_at(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.capacity
+    }
+}
+
+/// One token bucket per key, created on first use
+pub struct KeyedRateLimiter<K> {
+    capacity: f64,
+    refill_rate: f64,
+    buckets: HashMap<K, TokenBucket>,
+}
+
+impl<K: Hash + Eq> KeyedRateLimiter<K> {
+    pub fn new(capacity: f64, refill_rate: f64) -> Self {
+        KeyedRateLimiter {
+            capacity,
+            refill_rate,
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub fn check(&mut self, key: K) -> bool {
+        let now = Instant::now();
+        self.buckets
+            .entry(key)
+            .or_insert_with(|| TokenBucket::new_at(self.capacity, self.refill_rate, now))
+            .try_consume_at(1.0, now)
+    }
+
+    /// Forgets keys whose bucket has refilled completely
+    pub fn sweep(&mut self) {
+        let now = Instant::now();
+        self.buckets.retain(|_, bucket| !bucket.is_full_at(now));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageCategory {
+    TextMessage,
+    ChannelState,
+    UserState,
+    /// Limited by `plugin_data.rate` whenever relaying is enabled, not by these buckets
+    PluginData,
+    Query,
+    /// Metered against `voice_bandwidth` when relayed, not by these buckets
+    Voice,
+    Other,
+}
+
+impl MessageCategory {
+    pub fn of(message: &Message) -> Self {
+        match message {
+            Message::TextMessage(_) => MessageCategory::TextMessage,
+            Message::ChannelState(_) | Message::ChannelRemove(_) => MessageCategory::ChannelState,
+            Message::UserState(_) | Message::UserRemove(_) => MessageCategory::UserState,
+            Message::PluginDataTransmission(_) => MessageCategory::PluginData,
+            Message::ACL(_)
+            | Message::BanList(_)
+            | Message::QueryUsers(_)
+            | Message::UserList(_)
+            | Message::PermissionQuery(_)
+            | Message::UserStats(_)
+            | Message::RequestBlob(_) => MessageCategory::Query,
+            Message::UDPTunnel(_) => MessageCategory::Voice,
+            _ => MessageCategory::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitVerdict {
+    Allowed,
+    /// Over the message rate; the client should be told with PermissionDenied
+    Denied,
+    /// Too many violations; the client should be disconnected
+    Disconnect,
+}
+
+/// Control channel limits of a single session
+pub struct MessageRateLimiter {
+    buckets: HashMap<MessageCategory, TokenBucket>,
+    violations: TokenBucket,
+}
+
+impl MessageRateLimiter {
+    /// Returns `None` when rate limiting is disabled
+    pub fn new(config: &MessageRateLimitConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        let bucket = |limit: RateLimit| TokenBucket::new(limit.burst as f64, limit.per_second);
+        let buckets = HashMap::from([
+            (MessageCategory::TextMessage, bucket(config.text_message)),
+            (MessageCategory::ChannelState, bucket(config.channel_state)),
+            (MessageCategory::UserState, bucket(config.user_state)),
+            (MessageCategory::Query, bucket(config.query)),
+            (MessageCategory::Other, bucket(config.other)),
+        ]);
+
+        let violations = TokenBucket::new(
+            config.max_violations as f64,
+            config.max_violations as f64 / config.violation_window_secs.max(1) as f64,
+        );
+
+        Some(MessageRateLimiter { buckets, violations })
+    }
+
+    pub fn check(&mut self, message: &Message) -> RateLimitVerdict {
+        let category = MessageCategory::of(message);
+        let allowed = self
+            .buckets
+            .get_mut(&category)
+            .is_none_or(|bucket| bucket.try_consume(1.0));
+        if allowed {
+            RateLimitVerdict::Allowed
+        } else if self.violations.try_consume(1.0) {
+            RateLimitVerdict::Denied
+        } else {
+            RateLimitVerdict::Disconnect
+        }
+    }
+}
+
+/// The network an address belongs to, using separate prefix lengths per family
+pub fn address_prefix(address: IpAddr, ipv4_length: u8, ipv6_length: u8) -> IpCidr {
+    let address = address.to_canonical();
+    let length = match address {
+        IpAddr::V4(_) => ipv4_length.min(32),
+        IpAddr::V6(_) => ipv6_length.min(128),
+    };
+    IpInet::new(address, length).unwrap().network()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new_at(2.0, 1.0, start);
+
+        assert!(bucket.try_consume_at(1.0, start));
+        assert!(bucket.try_consume_at(1.0, start));
+        assert!(!bucket.try_consume_at(1.0, start));
+
+        assert!(!bucket.try_consume_at(1.0, start + Duration::from_millis(500)));
+        assert!(bucket.try_consume_at(1.0, start + Duration::from_millis(1500)));
+        assert!(bucket.is_full_at(start + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn repeated_violations_disconnect() {
+        let config = MessageRateLimitConfig {
+            enabled: true,
+            text_message: RateLimit { burst: 1, per_second: 0.0 },
+            max_violations: 2,
+            ..Default::default()
+        };
+        let mut limiter = MessageRateLimiter::new(&config).unwrap();
+        let text = Message::TextMessage(Default::default());
+
+        assert_eq!(limiter.check(&text), RateLimitVerdict::Allowed);
+        assert_eq!(limiter.check(&text), RateLimitVerdict::Denied);
+        assert_eq!(limiter.check(&text), RateLimitVerdict::Denied);
+        assert_eq!(limiter.check(&text), RateLimitVerdict::Disconnect);
+
+        // Other categories have their own buckets
+        let ping = Message::Ping(Default::default());
+        assert_eq!(limiter.check(&ping), RateLimitVerdict::Allowed);
+
+        // Voice is left to the bandwidth meter
+        let voice = Message::UDPTunnel(vec![0; 80]);
+        assert_eq!(limiter.check(&voice), RateLimitVerdict::Allowed);
+        assert_eq!(limiter.check(&voice), RateLimitVerdict::Allowed);
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        assert!(MessageRateLimiter::new(&MessageRateLimitConfig::default()).is_none());
+    }
+
+    #[test]
+    fn prefixes() {
+        assert_eq!(
+            address_prefix("2001:db8:1:2:3:4:5:6".parse().unwrap(), 32, 64).to_string(),
+            "2001:db8:1:2::/64"
+        );
+        assert_eq!(
+            address_prefix("::ffff:192.0.2.7".parse().unwrap(), 24, 64).to_string(),
+            "192.0.2.0/24"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::future::poll_fn;
 use std::net::ToSocketAddrs;
 use std::str::FromStr;
@@ -9,19 +10,58 @@ use rustls::pki_types::{pem::PemObject as _, CertificateDer, PrivateKeyDer};
 use rustls::server::WebPkiClientVerifier;
 use rustls::version::{TLS12, TLS13};
 use tokio::io::ReadBuf;
+use tokio::sync::{Mutex, OwnedSemaphorePermit, RwLock, RwLockReadGuard, Semaphore};
 use tokio_rustls::TlsAcceptor;
 
+use enumflags2::BitFlags;
+
+use crate::acl::{effective_permissions, ACLPermissions};
+use crate::admission::{AdmissionDecision, AdmissionPolicy};
+use crate::afk;
+use crate::announcements::Announcements;
+use crate::autoban::{AutobanEvent, AutobanTracker};
+use crate::bandwidth::BandwidthVerdict;
+use crate::audit::AuditLog;
+use crate::bans::Bans;
+use crate::blob_store::BlobStore;
+use crate::channels::{Channels, ROOT_CHANNEL_ID};
+use crate::chat_history::{ChatHistory, HistoryEntry};
+use crate::client::client::Client;
+use crate::client::client_session_identifier::ClientSessionIdentifier;
+use crate::client::group::ClientMembershipQuery;
 use crate::client::states::ConnectionState;
-use crate::client_certificate_verifier::ClientCertificateVerifier;
-use crate::constants::{release, APP_PROTO_VER};
-use crate::messages::{Message, WriteMessageExt};
-use crate::mumble_proto::Version;
+use crate::client::user_version::UserVersion;
+use crate::client_certificate_verifier::{ClientCertificateVerifier, ClientChainValidator};
+use crate::config::{
+    AfkConfig, ConnectionLimitsConfig, KeepaliveConfig, MailboxConfig, MessageRateLimitConfig, MessageSizeLimitsConfig,
+    PluginDataConfig, TextureConfig, VoiceBandwidthConfig,
+};
+use crate::constants::{
+    release, AFK_CHECK_INTERVAL, APP_PROTO_VER, BAN_PRUNE_INTERVAL, BLOB_COLLECT_INTERVAL, GEOIP_RELOAD_CHECK_INTERVAL,
+    RATE_LIMITER_SWEEP_INTERVAL,
+};
+use crate::geoip::GeoIpService;
+use crate::html_sanitizer::ContentKind;
+use crate::mailbox::MailStore;
+use crate::messages::{handlers, Message, WriteMessageExt};
+use crate::mumble_proto::{reject::RejectType, ChannelRemove, ChannelState, CodecVersion, Reject, TextMessage, UserRemove, UserState, Version};
 use crate::proxy_protocol::get_proxy_protocol_real_ip;
+use crate::texture::process_texture;
+use crate::rate_limit::{address_prefix, KeyedRateLimiter, MessageRateLimiter, RateLimitVerdict};
+use crate::user_registry::UserRegistry;
+use crate::voice;
 use crate::{
-    client_repository::ClientRepository, codec_info::CodecInfo, config::Config,
+    client_repository::{ClientRepository, SessionLimitExceeded}, codec_info::{CodecInfo, CELT_WARNING, OPUS_WARNING}, config::Config,
     types::NodeIdentifier,
 };
 
+pub struct ConnectionPolicy {
+    pub server_password: Option<String>,
+    pub require_certificate: bool,
+    pub require_verified_certificate: bool,
+    pub registered_users_only: bool,
+}
+
 pub struct Server {
     node_identifier: NodeIdentifier,
 
@@ -30,14 +70,41 @@ pub struct Server {
     send_build_info: bool,
     send_os_info: bool,
     allowed_proxies: Vec<AnyIpCidr>,
+    connection_policy: ConnectionPolicy,
+    admission: AdmissionPolicy,
+    limits: ConnectionLimitsConfig,
+    keepalive: KeepaliveConfig,
+    message_rate_limits: MessageRateLimitConfig,
+    message_size_limits: MessageSizeLimitsConfig,
+    plugin_data: PluginDataConfig,
+    mailbox: MailboxConfig,
+    textures: Arc<TextureConfig>,
+    afk: AfkConfig,
+    announcements: RwLock<Announcements>,
+    voice_bandwidth: VoiceBandwidthConfig,
 
     tcp_listener: tokio::net::TcpListener,
     tls_acceptor: TlsAcceptor,
     udp_socket: tokio::net::UdpSocket,
+    client_chain_validator: Option<ClientChainValidator>,
 
     clients: ClientRepository,
+    channels: RwLock<Channels>,
+    registry: UserRegistry,
+    bans: Bans,
+    geoip: GeoIpService,
+    chat_history: Option<ChatHistory>,
+    blobs: BlobStore,
+    mail: MailStore,
+    audit_log: AuditLog,
 
-    codec_info: CodecInfo,
+    connection_rate_limiter: Option<Mutex<KeyedRateLimiter<IpCidr>>>,
+    pending_connections: Option<Arc<Semaphore>>,
+    autoban: AutobanTracker,
+    plugin_data_limiter: Mutex<KeyedRateLimiter<u32>>,
+
+    opus_threshold: u16,
+    codec_info: Mutex<CodecInfo>,
 }
 
 impl Server {
@@ -53,6 +120,7 @@ impl Server {
             .iter()
             .map(|proxy| AnyIpCidr::from_str(proxy))
             .collect::<Result<Vec<_>, _>>()?;
+        let announcements = Announcements::from_config(&config)?;
 
         let certificate =
             CertificateDer::pem_file_iter(config.cert_path)?.collect::<Result<Vec<_>, _>>()?;
@@ -69,22 +137,167 @@ impl Server {
 
         let tls_acceptor = TlsAcceptor::from(Arc::new(tls_config));
 
+        let client_chain_validator = match &config.client_ca_path {
+            Some(ca_path) => Some(ClientChainValidator::from_pem_file(ca_path)?),
+            None if config.require_verified_certificate => {
+                return Err("require_verified_certificate is set but client_ca_path is missing".into())
+            }
+            None => None,
+        };
+
+        let admission = AdmissionPolicy::new(&config.admission)?;
+        let autoban = AutobanTracker::new(&config.autoban, &allowed_proxies)?;
+        let connection_rate_limiter = config.limits.connection_rate_per_minute.map(|rate| {
+            Mutex::new(KeyedRateLimiter::new(
+                config.limits.connection_burst as f64,
+                rate as f64 / 60.0,
+            ))
+        });
+        let plugin_data_limiter = Mutex::new(KeyedRateLimiter::new(
+            config.plugin_data.rate.burst as f64,
+            config.plugin_data.rate.per_second,
+        ));
+        let pending_connections = config
+            .limits
+            .max_pending_connections
+            .map(|max| Arc::new(Semaphore::new(max)));
+        let registry = UserRegistry::load(config.registry_path.as_deref())?;
+        let bans = Bans::load(config.ban_list_path.as_deref())?;
+        let chat_history = ChatHistory::load(&config.chat_history)?;
+        let blobs = BlobStore::open(config.blob_path.as_deref())?;
+        let mail = MailStore::open(config.mail_path.as_deref())?;
+        let geoip = GeoIpService::open(
+            config.geoip_city_path.as_deref(),
+            config.geoip_asn_path.as_deref(),
+        )?;
+
         Ok(Arc::new(Box::new(Server {
             node_identifier: config.node_id,
             allowed_proxies,
             send_version: config.send_version,
             send_build_info: config.send_build_info,
             send_os_info: config.send_os_info,
+            connection_policy: ConnectionPolicy {
+                server_password: config.server_password,
+                require_certificate: config.require_certificate,
+                require_verified_certificate: config.require_verified_certificate,
+                registered_users_only: config.registered_users_only,
+            },
+            admission,
+            limits: config.limits,
+            keepalive: config.keepalive,
+            message_rate_limits: config.message_rate_limits,
+            message_size_limits: config.message_size_limits,
+            plugin_data: config.plugin_data,
+            mailbox: config.mailbox,
+            textures: Arc::new(config.textures),
+            afk: config.afk,
+            announcements: RwLock::new(announcements),
+            voice_bandwidth: config.voice_bandwidth,
             tcp_listener,
             tls_acceptor,
             udp_socket,
+            client_chain_validator,
             clients: ClientRepository::new(config.node_id),
-            codec_info: CodecInfo::default(),
+            channels: RwLock::new(Channels::new(&config.register_name)),
+            registry,
+            bans,
+            geoip,
+            chat_history,
+            blobs,
+            mail,
+            audit_log: AuditLog::new(config.audit_log_path.as_deref()),
+            connection_rate_limiter,
+            pending_connections,
+            autoban,
+            plugin_data_limiter,
+            opus_threshold: config.opus_threshold,
+            codec_info: Mutex::new(CodecInfo::default()),
         })))
     }
 
     pub async fn run(self: Arc<Box<Self>>) -> Result<(), Box<dyn std::error::Error>> {
         println!("Server is running on {}", self.tcp_listener.local_addr()?);
+
+        let server = Arc::clone(&self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(BAN_PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                match server.bans.prune_expired().await {
+                    Ok(0) => {}
+                    Ok(pruned) => tracing::info!(pruned, "Pruned expired bans"),
+                    Err(e) => tracing::error!("Failed to prune expired bans: {}", e),
+                }
+            }
+        });
+
+        let server = Arc::clone(&self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RATE_LIMITER_SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Some(limiter) = &server.connection_rate_limiter {
+                    limiter.lock().await.sweep();
+                }
+                server.autoban.sweep().await;
+                server.plugin_data_limiter.lock().await.sweep();
+            }
+        });
+
+        let server = Arc::clone(&self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(BLOB_COLLECT_INTERVAL);
+            loop {
+                interval.tick().await;
+                let removed = server.collect_blobs().await;
+                if removed > 0 {
+                    tracing::info!(removed, "Removed unreferenced blobs");
+                }
+            }
+        });
+
+        #[cfg(unix)]
+        {
+            let server = Arc::clone(&self);
+            tokio::spawn(async move {
+                let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+                    Ok(hangup) => hangup,
+                    Err(e) => {
+                        tracing::error!("Failed to listen for SIGHUP: {}", e);
+                        return;
+                    }
+                };
+                while hangup.recv().await.is_some() {
+                    if let Err(e) = server.reload().await {
+                        tracing::error!("Failed to reload configuration: {}", e);
+                    }
+                }
+            });
+        }
+
+        if self.afk.enabled {
+            let server = Arc::clone(&self);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(AFK_CHECK_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    afk::check_idle_clients(&server).await;
+                }
+            });
+        }
+
+        if self.geoip.is_enabled() {
+            let server = Arc::clone(&self);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(GEOIP_RELOAD_CHECK_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    server.geoip.reload_if_changed().await;
+                }
+            });
+        }
+
         loop {
             let (tcp_stream, remote_addr) = self.tcp_listener.accept().await?;
             let server = Arc::clone(&self);
@@ -118,9 +331,71 @@ impl Server {
             remote_addr.ip()
         };
 
+        if let Some(ban) = self.bans.find_by_address(real_ip).await {
+            tracing::info!(%real_ip, reason = ban.reason, "Dropped connection from banned address");
+            return Ok(());
+        }
+
+        let geoip = self.geoip.lookup(real_ip).await;
+        tracing::info!(
+            %real_ip,
+            country = geoip.country_code.as_deref().unwrap_or("-"),
+            asn = geoip.asn.unwrap_or(0),
+            organization = geoip.organization.as_deref().unwrap_or("-"),
+            "Incoming connection"
+        );
+
+        if let AdmissionDecision::Deny { rule_index } = self.admission.evaluate(real_ip, &geoip) {
+            if self.admission.is_dry_run() {
+                tracing::info!(%real_ip, ?rule_index, "Admission policy would deny connection (dry run)");
+            } else {
+                tracing::info!(%real_ip, ?rule_index, "Connection denied by admission policy");
+                return Ok(());
+            }
+        }
+
+        // Only connections that got past bans and admission count towards flood detection
+        self.record_autoban_event(real_ip, AutobanEvent::Connection).await;
+
+        if let Some(limiter) = &self.connection_rate_limiter {
+            let prefix = address_prefix(
+                real_ip,
+                self.limits.ipv4_rate_prefix_length,
+                self.limits.ipv6_rate_prefix_length,
+            );
+            if !limiter.lock().await.check(prefix) {
+                tracing::info!(%real_ip, %prefix, "Connection rate limit exceeded");
+                return Ok(());
+            }
+        }
+
+        // Held until the client has authenticated
+        let mut pending_permit: Option<OwnedSemaphorePermit> = match &self.pending_connections {
+            Some(semaphore) => match Arc::clone(semaphore).try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    tracing::warn!(%real_ip, "Too many pending connections, dropping");
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
+
         let local_addr = tcp_stream.local_addr()?;
         let tls_acceptor = self.tls_acceptor.clone();
-        let mut tls_stream = tls_acceptor.accept(tcp_stream).await?;
+        let handshake_timeout = std::time::Duration::from_secs(self.limits.handshake_timeout_secs);
+        let mut tls_stream = match tokio::time::timeout(handshake_timeout, tls_acceptor.accept(tcp_stream)).await {
+            Ok(stream) => stream?,
+            Err(_) => {
+                tracing::info!(%real_ip, "TLS handshake timed out");
+                return Ok(());
+            }
+        };
+
+        let verified_certificate_chain = match (&self.client_chain_validator, tls_stream.get_ref().1.peer_certificates()) {
+            (Some(validator), Some(chain)) => validator.is_trusted(chain),
+            _ => false,
+        };
 
         let os_info = os_info::get();
 
@@ -156,49 +431,893 @@ impl Server {
 
         let client = self
             .clients
-            .allocate_local_client(real_ip, remote_addr, None, local_addr, tls_stream)
+            .allocate_local_client(
+                real_ip,
+                geoip,
+                remote_addr,
+                None,
+                local_addr,
+                tls_stream,
+                verified_certificate_chain,
+                &self.limits,
+            )
             .await;
+        let client = match client {
+            Ok(client) => client,
+            Err(SessionLimitExceeded { reason, mut connection }) => {
+                tracing::info!(%real_ip, reason, "Session limit reached");
+                let mut reject = Reject {
+                    r#type: None,
+                    reason: Some(reason.to_string()),
+                };
+                reject.set_type(RejectType::ServerFull);
+                connection.write_proto_message(&Message::Reject(reject)).await?;
+                return Ok(());
+            }
+        };
+        client.set_connection_state(ConnectionState::ServerSentVersion).await;
+
+        // Errors are stringified so that they can be held across the teardown
+        let result = self
+            .run_session(&client, real_ip, pending_permit)
+            .await
+            .map_err(|e| e.to_string());
+        self.teardown(&client).await;
+        result.map_err(Into::into)
+    }
+
+    /// Releases everything a session holds. Runs once per client, however the session ended.
+    async fn teardown(&self, client: &Arc<Box<Client>>) {
+        if !client.begin_teardown() {
+            return;
+        }
+
+        let session_id = client.get_session_id();
+        let was_authenticated = client.is_authenticated().await;
+        client.set_connection_state(ConnectionState::Dead).await;
+
+        let removal = client.take_removal().await;
+        let kicked = removal.is_some();
+        if was_authenticated {
+            let removal = removal.unwrap_or(UserRemove {
+                session: session_id,
+                actor: None,
+                reason: None,
+                ban: None,
+            });
+            // Broadcasts skip Dead clients, so a kicked client gets its notice directly
+            if kicked {
+                let _ = client.write_proto_message(&Message::UserRemove(removal.clone())).await;
+            }
+            self.broadcast(&Message::UserRemove(removal)).await;
+        }
+
+        let channel_id = client.get_current_channel_id().await;
+        if let Some(user_id) = client.get_user_id().await {
+            if let Err(e) = self.registry.set_last_channel(user_id, channel_id).await {
+                tracing::error!(session_id, user_id, "Failed to persist last channel: {}", e);
+            }
+        }
+
+        client.clear_voice_targets().await;
+        if let Ok(identifier) = ClientSessionIdentifier::try_from(session_id) {
+            self.clients.remove_client(identifier).await;
+        }
+
+        if was_authenticated {
+            self.release_temporary_channel(channel_id).await;
+            self.recheck_codecs(None).await;
+        }
+
+        if let Err(e) = client.close().await {
+            tracing::debug!(session_id, "Failed to close connection: {}", e);
+        }
+
+        tracing::info!(session_id, real_ip = %client.get_real_ip_address(), kicked, "Client disconnected");
+    }
+
+    /// Removes a temporary channel once its last user has left
+    async fn release_temporary_channel(&self, channel_id: u32) {
+        let is_temporary = self
+            .channels
+            .read()
+            .await
+            .get_channel(channel_id)
+            .is_some_and(|channel| channel.is_temporary());
+        if !is_temporary || self.count_channel_users(channel_id).await > 0 {
+            return;
+        }
+
+        for removed in self.remove_channel(channel_id).await {
+            self.broadcast(&Message::ChannelRemove(ChannelRemove { channel_id: removed }))
+                .await;
+        }
+    }
+
+    async fn run_session(
+        &self,
+        client: &Arc<Box<Client>>,
+        real_ip: std::net::IpAddr,
+        mut pending_permit: Option<OwnedSemaphorePermit>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let client = Arc::clone(client);
+        let mut message_rate_limiter = MessageRateLimiter::new(&self.message_rate_limits);
+        let authenticate_deadline = tokio::time::Instant::now()
+            + std::time::Duration::from_secs(self.limits.authenticate_timeout_secs);
 
         loop {
+            let authenticated = client.is_authenticated().await;
+            if authenticated {
+                pending_permit = None;
+            }
+
             // Handle incoming messages from the client
-            match client.read_proto_message().await {
-                Ok(message) => match message {
-                    Message::Version(version) => todo!(),
-                    Message::UDPTunnel(items) => todo!(),
-                    Message::Authenticate(authenticate) => todo!(),
-                    Message::Ping(ping) => todo!(),
-                    Message::Reject(reject) => todo!(),
-                    Message::ServerSync(server_sync) => todo!(),
-                    Message::ChannelRemove(channel_remove) => todo!(),
-                    Message::ChannelState(channel_state) => todo!(),
-                    Message::UserRemove(user_remove) => todo!(),
-                    Message::UserState(user_state) => todo!(),
-                    Message::BanList(ban_list) => todo!(),
-                    Message::TextMessage(text_message) => todo!(),
-                    Message::PermissionDenied(permission_denied) => todo!(),
-                    Message::ACL(acl) => todo!(),
-                    Message::QueryUsers(query_users) => todo!(),
-                    Message::CryptSetup(crypt_setup) => todo!(),
-                    Message::ContextActionModify(context_action_modify) => todo!(),
-                    Message::ContextAction(context_action) => todo!(),
-                    Message::UserList(user_list) => todo!(),
-                    Message::VoiceTarget(voice_target) => todo!(),
-                    Message::PermissionQuery(permission_query) => todo!(),
-                    Message::CodecVersion(codec_version) => todo!(),
-                    Message::UserStats(user_stats) => todo!(),
-                    Message::RequestBlob(request_blob) => todo!(),
-                    Message::ServerConfig(server_config) => todo!(),
-                    Message::SuggestConfig(suggest_config) => todo!(),
-                },
-                Err(e) => {
-                    break Err(format!("Error reading message from client: {:?}", e).into());
+            let read = async {
+                tokio::select! {
+                    result = client.read_proto_message(&self.message_size_limits) => {
+                        result.map(Some).map_err(|e| format!("{:?}", e))
+                    }
+                    _ = client.disconnected() => Ok(None),
+                }
+            };
+            let deadline = match (authenticated, self.keepalive.ping_timeout_secs) {
+                (false, _) => Some(authenticate_deadline),
+                (true, Some(timeout_secs)) => {
+                    let since_ping = (chrono::Utc::now() - client.get_last_ping().await)
+                        .to_std()
+                        .unwrap_or_default();
+                    let timeout = std::time::Duration::from_secs(timeout_secs);
+                    Some(tokio::time::Instant::now() + timeout.saturating_sub(since_ping))
+                }
+                (true, None) => None,
+            };
+            let result = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, read).await,
+                None => Ok(read.await),
+            };
+
+            let message = match result {
+                Ok(Ok(Some(message))) => message,
+                Ok(Ok(None)) => break Ok(()),
+                Ok(Err(e)) => {
+                    break Err(format!("Error reading message from client: {}", e).into());
+                }
+                Err(_) if authenticated => {
+                    tracing::info!(%real_ip, session_id = client.get_session_id(), "Ping timed out");
+                    break Ok(());
+                }
+                Err(_) => {
+                    tracing::info!(%real_ip, session_id = client.get_session_id(), "Authentication timed out");
+                    break Ok(());
+                }
+            };
+            client.record_tcp_packet(message.encoded_len()).await;
+
+            if let Some(limiter) = &mut message_rate_limiter {
+                match limiter.check(&message) {
+                    RateLimitVerdict::Allowed => {}
+                    RateLimitVerdict::Denied => {
+                        client.send_text_denied("You are sending messages too quickly").await?;
+                        continue;
+                    }
+                    RateLimitVerdict::Disconnect => {
+                        tracing::warn!(
+                            %real_ip,
+                            session_id = client.get_session_id(),
+                            message_type = message.proto_tag(),
+                            "Disconnecting client for exceeding message rate limits"
+                        );
+                        break Ok(());
+                    }
+                }
+            }
+
+            if afk::is_user_activity(&message) {
+                client.mark_active().await;
+            }
+
+            let message_type = message.proto_tag();
+            match message {
+                Message::Version(version) => {
+                    client.set_user_version(UserVersion::from(&version)).await;
+                    if client.get_connection_state().await == ConnectionState::ServerSentVersion {
+                        client.set_connection_state(ConnectionState::ClientSentVersion).await;
+                    }
+                }
+                Message::UDPTunnel(packet) => handlers::handle_udp_tunnel(self, &client, packet).await?,
+                Message::Authenticate(authenticate) => {
+                    handlers::handle_authenticate(self, &client, authenticate).await?
+                }
+                Message::Ping(ping) => handlers::handle_ping(self, &client, ping).await?,
+                Message::ChannelState(channel_state) => {
+                    handlers::handle_channel_state(self, &client, channel_state).await?
+                }
+                Message::UserRemove(user_remove) => {
+                    handlers::handle_user_remove(self, &client, user_remove).await?
+                }
+                Message::UserState(user_state) => {
+                    handlers::handle_user_state(self, &client, user_state).await?
+                }
+                Message::BanList(ban_list) => {
+                    handlers::handle_ban_list(self, &client, ban_list).await?
+                }
+                Message::TextMessage(text_message) => {
+                    handlers::handle_text_message(self, &client, text_message).await?
+                }
+                Message::QueryUsers(query_users) => handlers::handle_query_users(self, &client, query_users).await?,
+                Message::PermissionQuery(permission_query) => {
+                    handlers::handle_permission_query(self, &client, permission_query).await?
+                }
+                Message::UserStats(user_stats) => handlers::handle_user_stats(self, &client, user_stats).await?,
+                Message::RequestBlob(request_blob) => {
+                    handlers::handle_request_blob(self, &client, request_blob).await?
+                }
+                Message::PluginDataTransmission(plugin_data) => {
+                    handlers::handle_plugin_data_transmission(self, &client, plugin_data).await?
+                }
+                // Edits this server does not support yet
+                Message::ChannelRemove(_) | Message::ACL(_) | Message::UserList(_) => {
+                    tracing::debug!(session_id = client.get_session_id(), message_type, "Denied unsupported request");
+                    client.send_text_denied("This server does not support that operation").await?
+                }
+                // UDP voice and whisper targets are not supported yet, context actions are never registered
+                Message::CryptSetup(_) | Message::VoiceTarget(_) | Message::ContextAction(_) => {
+                    tracing::debug!(session_id = client.get_session_id(), message_type, "Ignored unsupported message");
+                }
+                // Only ever sent by the server
+                Message::Reject(_)
+                | Message::ServerSync(_)
+                | Message::PermissionDenied(_)
+                | Message::CodecVersion(_)
+                | Message::ServerConfig(_)
+                | Message::SuggestConfig(_)
+                | Message::ContextActionModify(_) => {
+                    tracing::debug!(session_id = client.get_session_id(), message_type, "Ignored server-only message");
+                }
+            }
+        }
+    }
+
+    pub async fn record_autoban_event(&self, address: std::net::IpAddr, event: AutobanEvent) {
+        let Some(autoban) = self.autoban.record(address, event).await else {
+            return;
+        };
+
+        tracing::warn!(
+            %address,
+            prefix = %autoban.prefix,
+            connections = autoban.connections,
+            failed_authentications = autoban.failed_authentications,
+            duration = autoban.ban.duration,
+            ?event,
+            "Autoban issued"
+        );
+
+        match self.bans.add(autoban.ban).await {
+            Ok(true) => {}
+            Ok(false) => tracing::debug!(%address, "Autoban already in place"),
+            Err(e) => tracing::error!("Failed to persist autoban: {}", e),
+        }
+    }
+
+    pub fn get_connection_policy(&self) -> &ConnectionPolicy {
+        &self.connection_policy
+    }
+
+    pub fn get_clients(&self) -> &ClientRepository {
+        &self.clients
+    }
+
+    pub fn get_registry(&self) -> &UserRegistry {
+        &self.registry
+    }
+
+    pub fn get_bans(&self) -> &Bans {
+        &self.bans
+    }
+
+    pub fn get_plugin_data_config(&self) -> &PluginDataConfig {
+        &self.plugin_data
+    }
+
+    pub async fn get_announcements(&self) -> RwLockReadGuard<'_, Announcements> {
+        self.announcements.read().await
+    }
+
+    pub fn get_voice_bandwidth_config(&self) -> &VoiceBandwidthConfig {
+        &self.voice_bandwidth
+    }
+
+    /// Applies the advertised bandwidth cap to an incoming voice packet.
+    /// Returns whether the packet may be relayed.
+    async fn check_voice_bandwidth(&self, client: &Client, size: usize) -> bool {
+        if !self.voice_bandwidth.enabled {
+            return true;
+        }
+
+        let max_bandwidth = self.announcements.read().await.get_max_bandwidth();
+        let max_bytes_per_sec = self.voice_bandwidth.max_bytes_per_sec(max_bandwidth);
+        match client.check_voice_bandwidth(&self.voice_bandwidth, size, max_bytes_per_sec).await {
+            BandwidthVerdict::Accept => true,
+            BandwidthVerdict::Drop => false,
+            BandwidthVerdict::DropAndWarn => {
+                let session_id = client.get_session_id();
+                tracing::warn!(session_id, max_bandwidth, "Dropping voice over the bandwidth limit");
+                let warning = format!(
+                    "Your voice exceeds the server's bandwidth limit of {} kbit/s and is being dropped. Please lower your audio quality.",
+                    max_bandwidth / 1000
+                );
+                if let Err(e) = client.send_server_message(&warning).await {
+                    tracing::debug!(session_id, "Failed to send bandwidth warning: {}", e);
                 }
+                false
             }
         }
     }
 
-    pub async fn reload(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        // self.config = Config::load();
+    /// Forwards a voice packet tunneled by `client` to everyone who should hear it.
+    /// Pings are echoed back; voice from muted speakers is discarded before it is
+    /// metered against the bandwidth cap. Voice only arrives through the TCP tunnel:
+    /// receiving it over UDP would need the OCB2 voice crypto, which is not implemented.
+    pub async fn relay_voice(&self, client: &Client, packet: &[u8]) {
+        let Some(&header) = packet.first() else {
+            return;
+        };
+        let session_id = client.get_session_id();
+        if voice::packet_type(header) == voice::TYPE_PING {
+            if let Err(e) = client.write_proto_message(&Message::UDPTunnel(packet.to_vec())).await {
+                tracing::debug!(session_id, "Failed to echo voice ping: {}", e);
+            }
+            return;
+        }
+
+        {
+            let states = client.get_session_states().await;
+            if states.is_mute() || states.is_self_mute() || states.is_suppress() {
+                return;
+            }
+        }
+        if !self.check_voice_bandwidth(client, packet.len()).await {
+            return;
+        }
+
+        let relayed = Message::UDPTunnel(voice::relay_packet(packet, session_id, voice::TARGET_NORMAL));
+        let recipients = match voice::target(header) {
+            voice::TARGET_NORMAL => {
+                let channel_id = client.get_current_channel_id().await;
+                let mut recipients = Vec::new();
+                for other in self.clients.get_clients().await {
+                    if other.get_session_id() == session_id || !other.is_authenticated().await {
+                        continue;
+                    }
+                    let hears = other.get_current_channel_id().await == channel_id
+                        || other.get_listening_channel_ids().await.contains(&channel_id);
+                    let deaf = {
+                        let states = other.get_session_states().await;
+                        states.is_deaf() || states.is_self_deaf()
+                    };
+                    if hears && !deaf {
+                        recipients.push(other);
+                    }
+                }
+                recipients
+            }
+            voice::TARGET_LOOPBACK => {
+                if let Err(e) = client.write_proto_message(&relayed).await {
+                    tracing::debug!(session_id, "Failed to loop back voice: {}", e);
+                }
+                return;
+            }
+            // Whisper targets are registered through VoiceTarget, which is not supported yet
+            _ => return,
+        };
+
+        for other in recipients {
+            if let Err(e) = other.write_proto_message(&relayed).await {
+                tracing::debug!(session_id, other = other.get_session_id(), "Failed to relay voice: {}", e);
+            }
+        }
+    }
+
+    pub fn get_afk_config(&self) -> &AfkConfig {
+        &self.afk
+    }
+
+    pub fn get_mailbox_config(&self) -> &MailboxConfig {
+        &self.mailbox
+    }
+
+    /// Stores a channel message for replay; private messages are never recorded
+    pub async fn record_chat_history(&self, channel_ids: &[u32], sender_name: String, message: &str) {
+        let Some(history) = &self.chat_history else {
+            return;
+        };
+
+        let entry = HistoryEntry {
+            sender_name,
+            message: message.to_string(),
+            time: chrono::Utc::now(),
+        };
+        if let Err(e) = history.record(channel_ids, entry).await {
+            tracing::error!("Failed to persist chat history: {}", e);
+        }
+    }
+
+    /// Sends the recent messages of a channel to a client entering it
+    pub async fn replay_chat_history(&self, client: &Client, channel_id: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(history) = &self.chat_history else {
+            return Ok(());
+        };
+        if !self.has_permission(client, channel_id, ACLPermissions::TextMessage).await {
+            return Ok(());
+        }
+
+        for entry in history.recent(channel_id).await {
+            let message = format!(
+                "<i>[history {}] {}:</i> {}",
+                entry.time.format("%Y-%m-%d %H:%M UTC"),
+                ammonia::clean_text(&entry.sender_name),
+                entry.message
+            );
+            client
+                .write_proto_message(&Message::TextMessage(TextMessage {
+                    actor: None,
+                    session: Vec::new(),
+                    channel_id: vec![channel_id],
+                    tree_id: Vec::new(),
+                    message,
+                }))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes a channel and its subchannels, dropping their chat history
+    pub async fn remove_channel(&self, channel_id: u32) -> Vec<u32> {
+        let removed = self.channels.write().await.remove_tree(channel_id);
+        if let Some(history) = &self.chat_history {
+            if let Err(e) = history.purge_channels(&removed).await {
+                tracing::error!("Failed to persist chat history: {}", e);
+            }
+        }
+        removed
+    }
+
+    pub fn get_audit_log(&self) -> &AuditLog {
+        &self.audit_log
+    }
+
+    pub fn get_blobs(&self) -> &BlobStore {
+        &self.blobs
+    }
+
+    pub fn get_mail_store(&self) -> &MailStore {
+        &self.mail
+    }
+
+    async fn collect_blobs(&self) -> usize {
+        let mut live = HashSet::new();
+        for client in self.clients.get_clients().await {
+            live.extend(client.get_comment_hash().await);
+            live.extend(client.get_texture_hash().await);
+        }
+        live.extend(
+            self.channels
+                .read()
+                .await
+                .get_channels()
+                .filter_map(|channel| channel.get_description_hash().map(<[u8]>::to_vec)),
+        );
+
+        live.extend(self.registry.get_blob_hashes().await);
+
+        self.blobs.retain(&live, BLOB_COLLECT_INTERVAL).await
+    }
+
+    /// Validates an uploaded texture off the reactor, stores it and announces the new hash.
+    /// An empty texture clears it.
+    pub async fn set_user_texture(&self, client: &Client, actor: u32, data: Vec<u8>) -> Result<(), Box<dyn std::error::Error>> {
+        let config = Arc::clone(&self.textures);
+        let texture = tokio::task::spawn_blocking(move || process_texture(data, &config))
+            .await?
+            .map_err(|e| e.to_string())?;
+
+        let hash = match texture.is_empty() {
+            true => None,
+            false => Some(self.blobs.put(texture).await?),
+        };
+        client.set_texture_hash(hash.clone()).await;
+        if let Some(user_id) = client.get_user_id().await {
+            if let Err(e) = self.registry.set_texture_hash(user_id, hash.as_deref()).await {
+                tracing::error!(session_id = client.get_session_id(), user_id, "Failed to persist texture: {}", e);
+            }
+        }
+
+        self.broadcast(&Message::UserState(UserState {
+            session: Some(client.get_session_id()),
+            actor: Some(actor),
+            texture: hash.is_none().then(Vec::new),
+            texture_hash: hash,
+            ..Default::default()
+        }))
+        .await;
         Ok(())
     }
+
+    pub async fn count_channel_users(&self, channel_id: u32) -> usize {
+        let mut count = 0;
+        for client in self.clients.get_clients().await {
+            if client.is_authenticated().await && client.get_current_channel_id().await == channel_id {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Whether a channel has reached its user limit
+    pub async fn is_channel_full(&self, channel_id: u32) -> bool {
+        let max_users = match self.channels.read().await.get_channel(channel_id) {
+            Some(channel) => channel.get_max_users(),
+            None => return false,
+        };
+        max_users != 0 && self.count_channel_users(channel_id).await >= max_users as usize
+    }
+
+    /// Moves a user whose move has already been permitted and announces it, together with
+    /// any other changes in `state`, as done by `actor`. Listening to the new channel is
+    /// dropped, its chat history is replayed and a temporary channel left empty is removed.
+    pub async fn move_user(
+        &self,
+        target: &Client,
+        channel_id: u32,
+        actor: u32,
+        mut state: UserState,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let session_id = target.get_session_id();
+        let previous_channel_id = target.get_current_channel_id().await;
+        target.set_current_channel_id(channel_id).await;
+
+        if target.get_listening_channel_ids().await.contains(&channel_id) {
+            target.unlisten_channel(channel_id).await;
+            state.listening_channel_remove.push(channel_id);
+        }
+
+        state.session = Some(session_id);
+        state.actor = Some(actor);
+        state.channel_id = Some(channel_id);
+        self.broadcast(&Message::UserState(state)).await;
+        tracing::info!(session_id, actor, channel_id, "Moved user");
+
+        self.release_temporary_channel(previous_channel_id).await;
+        self.replay_chat_history(target, channel_id).await
+    }
+
+    /// The codec versions currently announced to clients
+    pub async fn get_codec_version(&self) -> CodecVersion {
+        self.codec_info.lock().await.to_codec_version()
+    }
+
+    /// Re-runs the codec vote and broadcasts CodecVersion if it changed. Clients left
+    /// without a usable codec are warned; if nothing changed only `joining` is checked.
+    pub async fn recheck_codecs(&self, joining: Option<&Client>) -> bool {
+        let mut clients = Vec::new();
+        for client in self.clients.get_clients().await {
+            if client.is_authenticated().await {
+                let (celt_versions, opus) = client.get_codecs().await;
+                clients.push((client, celt_versions, opus));
+            }
+        }
+
+        let (changed, codec_info) = {
+            let mut codec_info = self.codec_info.lock().await;
+            let changed = codec_info.vote(
+                clients.iter().map(|(_, celt_versions, opus)| (celt_versions.as_slice(), *opus)),
+                self.opus_threshold,
+            );
+            (changed, *codec_info)
+        };
+
+        if changed {
+            tracing::info!(opus = codec_info.is_opus(), "Codec version changed");
+            self.broadcast(&Message::CodecVersion(codec_info.to_codec_version())).await;
+        }
+        let warning = match codec_info.is_opus() {
+            true => OPUS_WARNING,
+            false => CELT_WARNING,
+        };
+        for (client, celt_versions, opus) in &clients {
+            let affected = match joining {
+                _ if changed => true,
+                Some(joining) => joining.get_session_id() == client.get_session_id(),
+                None => false,
+            };
+            if affected && !codec_info.can_decode(celt_versions, *opus) {
+                if let Err(e) = client.send_server_message(warning).await {
+                    tracing::debug!(session_id = client.get_session_id(), "Failed to send codec warning: {}", e);
+                }
+            }
+        }
+        changed
+    }
+
+    /// Sends a message to every authenticated client
+    pub async fn broadcast(&self, message: &Message) {
+        for client in self.clients.get_clients().await {
+            if !client.is_authenticated().await {
+                continue;
+            }
+            if let Err(e) = client.write_proto_message(message).await {
+                tracing::debug!(session_id = client.get_session_id(), "Failed to broadcast message: {}", e);
+            }
+        }
+    }
+
+    /// Sanitizes and stores a channel description and announces the new hash.
+    /// An empty description clears it.
+    pub async fn set_channel_description(&self, channel_id: u32, description: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let description = self
+            .announcements
+            .read()
+            .await
+            .get_html_sanitizer()
+            .sanitize(ContentKind::ChannelDescription, description)
+            .ok_or("Channel description is empty after sanitization")?;
+        let hash = match description.is_empty() {
+            true => None,
+            false => Some(self.blobs.put(description.into_bytes()).await?),
+        };
+
+        match self.channels.write().await.get_channel_mut(channel_id) {
+            Some(channel) => channel.set_description_hash(hash.clone()),
+            None => return Err(format!("No channel with ID {}", channel_id).into()),
+        }
+
+        // Clients fetch the description itself through RequestBlob
+        self.broadcast(&Message::ChannelState(ChannelState {
+            channel_id: Some(channel_id),
+            description: hash.is_none().then(String::new),
+            description_hash: hash,
+            ..Default::default()
+        }))
+        .await;
+        Ok(())
+    }
+
+    /// Channel and user states sent to a client that just authenticated, parents before children
+    pub async fn get_sync_messages(&self) -> Vec<Message> {
+        let mut messages = {
+            let channels = self.channels.read().await;
+            channels
+                .get_tree_ids(ROOT_CHANNEL_ID)
+                .into_iter()
+                .filter_map(|id| channels.get_channel(id))
+                .map(|channel| Message::ChannelState(channel.to_channel_state()))
+                .collect::<Vec<_>>()
+        };
+
+        for client in self.clients.get_clients().await {
+            if client.is_authenticated().await {
+                messages.push(Message::UserState(client.to_user_state().await));
+            }
+        }
+
+        messages
+    }
+
+    pub async fn get_channels(&self) -> RwLockReadGuard<'_, Channels> {
+        self.channels.read().await
+    }
+
+    /// Consumes one token from the sender's plugin data bucket
+    pub async fn check_plugin_data_rate(&self, session_id: u32) -> bool {
+        self.plugin_data_limiter.lock().await.check(session_id)
+    }
+
+    pub async fn get_permissions(&self, client: &Client, channel_id: u32) -> BitFlags<ACLPermissions> {
+        let groups = client.get_groups_clone().await.unwrap_or_default();
+        let groups = groups.iter().map(String::as_str).collect::<Vec<_>>();
+        let tokens = client.get_tokens().await.unwrap_or_default();
+        let tokens = tokens.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let query = ClientMembershipQuery::new(
+            &groups,
+            client.is_authenticated().await,
+            &tokens,
+            client.get_certificate_hash(),
+            client.is_verified(),
+            Some(client.get_real_ip_address()),
+            client.get_geoip().asn,
+            client.get_geoip().country_code.as_deref(),
+        );
+
+        let user_id = client.get_user_id().await;
+        let channels = self.channels.read().await;
+        effective_permissions(&channels, channel_id, user_id, &query)
+    }
+
+    pub async fn has_permission(&self, client: &Client, channel_id: u32, permission: ACLPermissions) -> bool {
+        self.get_permissions(client, channel_id).await.contains(permission)
+    }
+
+    /// Re-reads the configuration, applies the text message and HTML limits and announces
+    /// the server settings to everyone. Other settings take effect on restart.
+    pub async fn reload(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let config = Config::try_load()?;
+        let announcements = Announcements::from_config(&config)?;
+        let server_config = announcements.get_full_config();
+        let suggest_config = announcements.get_suggest_config();
+        *self.announcements.write().await = announcements;
+
+        self.broadcast(&Message::ServerConfig(server_config)).await;
+        if let Some(suggest_config) = suggest_config {
+            self.broadcast(&Message::SuggestConfig(suggest_config)).await;
+        }
+        tracing::info!("Reloaded configuration");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rustls::pki_types::ServerName;
+    use tokio_rustls::{client::TlsStream, TlsConnector};
+
+    use super::*;
+    use crate::config::MessageSizeLimitsConfig;
+    use crate::messages::ReadMessageExt;
+    use crate::mumble_proto::{Acl, Authenticate, CryptSetup, PermissionQuery, ServerSync, VoiceTarget};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    async fn start_server(directory: &std::path::Path) -> Arc<Box<Server>> {
+        let certificate = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        std::fs::create_dir_all(directory).unwrap();
+        let cert_path = directory.join("cert.pem");
+        let key_path = directory.join("key.pem");
+        std::fs::write(&cert_path, certificate.cert.pem()).unwrap();
+        std::fs::write(&key_path, certificate.signing_key.serialize_pem()).unwrap();
+
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "node_id": 1,
+            "listen": "127.0.0.1:0",
+            "opus_threshold": 100,
+            "register_name": "Root",
+            "cert_path": cert_path,
+            "key_path": key_path,
+            "send_version": true,
+            "send_build_info": false,
+            "send_os_info": false,
+            "allowed_proxies": [],
+        }))
+        .unwrap();
+
+        let server = Server::new(config).await.unwrap();
+        let running = Arc::clone(&server);
+        tokio::spawn(async move { running.run().await.map_err(|e| e.to_string()) });
+        server
+    }
+
+    async fn connect(server: &Server, username: &str) -> (TlsStream<tokio::net::TcpStream>, u32) {
+        #[derive(Debug)]
+        struct AcceptAnyCertificate(Arc<rustls::crypto::CryptoProvider>);
+
+        impl rustls::client::danger::ServerCertVerifier for AcceptAnyCertificate {
+            fn verify_server_cert(
+                &self,
+                _end_entity: &CertificateDer<'_>,
+                _intermediates: &[CertificateDer<'_>],
+                _server_name: &ServerName<'_>,
+                _ocsp_response: &[u8],
+                _now: rustls::pki_types::UnixTime,
+            ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+                Ok(rustls::client::danger::ServerCertVerified::assertion())
+            }
+
+            fn verify_tls12_signature(
+                &self,
+                message: &[u8],
+                cert: &CertificateDer<'_>,
+                dss: &rustls::DigitallySignedStruct,
+            ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+                rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+            }
+
+            fn verify_tls13_signature(
+                &self,
+                message: &[u8],
+                cert: &CertificateDer<'_>,
+                dss: &rustls::DigitallySignedStruct,
+            ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+                rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+            }
+
+            fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+                self.0.signature_verification_algorithms.supported_schemes()
+            }
+        }
+
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        let tls_config = rustls::ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+            .with_no_client_auth();
+        let tcp_stream = tokio::net::TcpStream::connect(server.tcp_listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let mut stream = TlsConnector::from(Arc::new(tls_config))
+            .connect(ServerName::try_from("localhost").unwrap(), tcp_stream)
+            .await
+            .unwrap();
+
+        stream
+            .write_proto_message(&Message::Version(Version {
+                version_v1: Some(APP_PROTO_VER.into()),
+                ..Default::default()
+            }))
+            .await
+            .unwrap();
+        stream
+            .write_proto_message(&Message::Authenticate(Authenticate {
+                username: Some(username.to_string()),
+                opus: Some(true),
+                ..Default::default()
+            }))
+            .await
+            .unwrap();
+
+        loop {
+            if let Message::ServerSync(ServerSync { session: Some(session), .. }) = read(&mut stream).await {
+                return (stream, session);
+            }
+        }
+    }
+
+    async fn read(stream: &mut TlsStream<tokio::net::TcpStream>) -> Message {
+        tokio::time::timeout(TIMEOUT, stream.read_proto_message(&MessageSizeLimitsConfig::default(), true))
+            .await
+            .expect("timed out waiting for a message")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn unhandled_messages_keep_the_session_and_teardown_runs_once() {
+        let directory = std::env::temp_dir().join(format!("server-session-{}", std::process::id()));
+        let server = start_server(&directory).await;
+        let (mut alice, alice_session) = connect(&server, "alice").await;
+        let (mut bob, _) = connect(&server, "bob").await;
+
+        for message in [
+            Message::VoiceTarget(VoiceTarget { id: Some(1), targets: Vec::new() }),
+            Message::CryptSetup(CryptSetup::default()),
+            Message::ServerSync(ServerSync::default()),
+            Message::ACL(Acl { channel_id: 0, query: Some(true), ..Default::default() }),
+            Message::PermissionQuery(PermissionQuery { channel_id: Some(0), ..Default::default() }),
+        ] {
+            alice.write_proto_message(&message).await.unwrap();
+        }
+        loop {
+            if let Message::PermissionQuery(query) = read(&mut alice).await {
+                assert_eq!(query.channel_id, Some(0));
+                break;
+            }
+        }
+        assert_eq!(server.clients.get_clients().await.len(), 2);
+
+        drop(alice);
+        let mut removals = 0;
+        while let Ok(Ok(message)) =
+            tokio::time::timeout(Duration::from_millis(500), bob.read_proto_message(&MessageSizeLimitsConfig::default(), true)).await
+        {
+            if matches!(message, Message::UserRemove(UserRemove { session, .. }) if session == alice_session) {
+                removals += 1;
+            }
+        }
+        assert_eq!(removals, 1);
+        assert_eq!(server.clients.get_clients().await.len(), 1);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
 }
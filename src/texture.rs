@@ -0,0 +1,182 @@
+use std::io::{Cursor, Read};
+
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageFormat, ImageReader, Limits};
+
+use crate::config::TextureConfig;
+
+/// Pre-1.2.4 clients send a raw 600x60 BGRA image, usually wrapped by Qt's `qCompress`
+const LEGACY_TEXTURE_LENGTH: usize = 600 * 60 * 4;
+
+/// Largest image decoded at all, even when transcoding is enabled
+const MAX_DECODE_DIMENSION: u32 = 4096;
+
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TextureError {
+    #[error("texture is not a PNG, JPEG or legacy raw image")]
+    UnsupportedFormat,
+    #[error("texture could not be decoded: {0}")]
+    Undecodable(#[from] image::ImageError),
+    #[error("texture is {width}x{height}, larger than allowed")]
+    DimensionsTooLarge { width: u32, height: u32 },
+    #[error("texture is {0} bytes, larger than allowed")]
+    TooLarge(usize),
+}
+
+fn is_legacy_texture(data: &[u8]) -> bool {
+    if data.len() == LEGACY_TEXTURE_LENGTH {
+        return true;
+    }
+
+    // qCompress: big-endian uncompressed length followed by a zlib stream
+    let Some((length, compressed)) = data.split_first_chunk::<4>() else {
+        return false;
+    };
+    if u32::from_be_bytes(*length) as usize != LEGACY_TEXTURE_LENGTH {
+        return false;
+    }
+
+    let mut decompressed = Vec::new();
+    let mut decoder = flate2::read::ZlibDecoder::new(compressed).take(LEGACY_TEXTURE_LENGTH as u64 + 1);
+    decoder.read_to_end(&mut decompressed).is_ok() && decompressed.len() == LEGACY_TEXTURE_LENGTH
+}
+
+fn encode(image: &DynamicImage) -> Result<Vec<u8>, TextureError> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+
+    let mut jpeg = Vec::new();
+    image
+        .to_rgb8()
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY))?;
+
+    // Keep transparency unless the JPEG is substantially smaller
+    Ok(match jpeg.len() * 2 < png.len() {
+        true => jpeg,
+        false => png,
+    })
+}
+
+/// Validates an uploaded texture and returns the bytes to store.
+///
+/// This decodes images and may be slow, so call it from a blocking task.
+pub fn process_texture(data: Vec<u8>, config: &TextureConfig) -> Result<Vec<u8>, TextureError> {
+    if data.is_empty() {
+        return Ok(data);
+    }
+
+    if is_legacy_texture(&data) {
+        return match config.allow_legacy {
+            true => Ok(data),
+            false => Err(TextureError::UnsupportedFormat),
+        };
+    }
+
+    let reader = ImageReader::new(Cursor::new(&data)).with_guessed_format().map_err(image::ImageError::IoError)?;
+    if !matches!(reader.format(), Some(ImageFormat::Png | ImageFormat::Jpeg)) {
+        return Err(TextureError::UnsupportedFormat);
+    }
+
+    let (width, height) = reader.into_dimensions()?;
+    let fits = width <= config.max_width && height <= config.max_height;
+    if fits && data.len() <= config.max_bytes {
+        // Decode anyway so that corrupt data never reaches other clients
+        let mut reader = ImageReader::new(Cursor::new(&data)).with_guessed_format().map_err(image::ImageError::IoError)?;
+        reader.limits(decode_limits(config.max_width, config.max_height));
+        reader.decode()?;
+        return Ok(data);
+    }
+
+    if !config.transcode {
+        return match fits {
+            true => Err(TextureError::TooLarge(data.len())),
+            false => Err(TextureError::DimensionsTooLarge { width, height }),
+        };
+    }
+
+    if width > MAX_DECODE_DIMENSION || height > MAX_DECODE_DIMENSION {
+        return Err(TextureError::DimensionsTooLarge { width, height });
+    }
+
+    let mut reader = ImageReader::new(Cursor::new(&data)).with_guessed_format().map_err(image::ImageError::IoError)?;
+    reader.limits(decode_limits(MAX_DECODE_DIMENSION, MAX_DECODE_DIMENSION));
+    let mut image = reader.decode()?;
+    if !fits {
+        image = image.resize(config.max_width, config.max_height, FilterType::Triangle);
+    }
+
+    let encoded = encode(&image)?;
+    match encoded.len() <= config.max_bytes {
+        true => Ok(encoded),
+        false => Err(TextureError::TooLarge(encoded.len())),
+    }
+}
+
+fn decode_limits(max_width: u32, max_height: u32) -> Limits {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(max_width);
+    limits.max_image_height = Some(max_height);
+    limits.max_alloc = Some(max_width as u64 * max_height as u64 * 8);
+    limits
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = DynamicImage::new_rgba8(width, height);
+        let mut data = Vec::new();
+        image.write_to(&mut Cursor::new(&mut data), ImageFormat::Png).unwrap();
+        data
+    }
+
+    fn config(transcode: bool) -> TextureConfig {
+        TextureConfig {
+            max_width: 64,
+            max_height: 64,
+            transcode,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn accepts_small_images_unchanged() {
+        let data = png(32, 32);
+        assert_eq!(process_texture(data.clone(), &config(false)).unwrap(), data);
+    }
+
+    #[test]
+    fn downscales_when_transcoding() {
+        let processed = process_texture(png(256, 128), &config(true)).unwrap();
+        let image = image::load_from_memory(&processed).unwrap();
+        assert_eq!((image.width(), image.height()), (64, 32));
+
+        assert!(matches!(
+            process_texture(png(256, 128), &config(false)),
+            Err(TextureError::DimensionsTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(process_texture(b"not an image".to_vec(), &config(true)).is_err());
+
+        let mut truncated = png(32, 32);
+        truncated.truncate(truncated.len() / 2);
+        assert!(process_texture(truncated, &config(true)).is_err());
+    }
+
+    #[test]
+    fn accepts_compressed_legacy_texture() {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&[0; LEGACY_TEXTURE_LENGTH]).unwrap();
+        let mut data = (LEGACY_TEXTURE_LENGTH as u32).to_be_bytes().to_vec();
+        data.extend(encoder.finish().unwrap());
+
+        assert_eq!(process_texture(data.clone(), &config(false)).unwrap(), data);
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisteredUser {
+    pub user_id: u32,
+    pub name: String,
+    /// Hex encoded SHA-1 of the client certificate
+    #[serde(default)]
+    pub certificate_hash: Option<String>,
+    #[serde(default)]
+    pub groups: HashSet<String>,
+    /// Channel the user was in when they last disconnected
+    #[serde(default)]
+    pub last_channel_id: Option<u32>,
+    /// Hex encoded blob store key of the comment
+    #[serde(default)]
+    pub comment_hash: Option<String>,
+    /// Hex encoded blob store key of the texture
+    #[serde(default)]
+    pub texture_hash: Option<String>,
+}
+
+pub struct UserRegistry {
+    path: Option<PathBuf>,
+    users: RwLock<HashMap<u32, RegisteredUser>>,
+}
+
+/// Names are compared case-insensitively and without surrounding whitespace.
+pub fn normalize_name(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+impl UserRegistry {
+    pub fn new() -> Self {
+        UserRegistry {
+            path: None,
+            users: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn load(path: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        let Some(path) = path else {
+            return Ok(Self::new());
+        };
+
+        let path = PathBuf::from(path);
+        let users: Vec<RegisteredUser> = match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(UserRegistry {
+            path: Some(path),
+            users: RwLock::new(users.into_iter().map(|u| (u.user_id, u)).collect()),
+        })
+    }
+
+    pub fn get_path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    async fn save(&self, users: &HashMap<u32, RegisteredUser>) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = &self.path {
+            let mut users = users.values().collect::<Vec<_>>();
+            users.sort_by_key(|u| u.user_id);
+            tokio::fs::write(path, serde_json::to_vec_pretty(&users)?).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get(&self, user_id: u32) -> Option<RegisteredUser> {
+        self.users.read().await.get(&user_id).cloned()
+    }
+
+    pub async fn get_by_name(&self, name: &str) -> Option<RegisteredUser> {
+        let name = normalize_name(name);
+        self.users
+            .read()
+            .await
+            .values()
+            .find(|u| normalize_name(&u.name) == name)
+            .cloned()
+    }
+
+    /// Resolves IDs to names and names to IDs in one pass. Unknown entries are left out.
+    pub async fn resolve(&self, ids: &[u32], names: &[String]) -> Vec<(u32, String)> {
+        let users = self.users.read().await;
+        let mut resolved = ids
+            .iter()
+            .filter_map(|id| users.get(id).map(|u| (u.user_id, u.name.clone())))
+            .collect::<Vec<_>>();
+
+        for name in names {
+            let name = normalize_name(name);
+            if let Some(user) = users.values().find(|u| normalize_name(&u.name) == name) {
+                resolved.push((user.user_id, user.name.clone()));
+            }
+        }
+        resolved
+    }
+
+    pub async fn get_by_certificate_hash(&self, hash: &[u8]) -> Option<RegisteredUser> {
+        let hash = hex::encode(hash);
+        self.users
+            .read()
+            .await
+            .values()
+            .find(|u| {
+                u.certificate_hash
+                    .as_deref()
+                    .is_some_and(|h| h.eq_ignore_ascii_case(&hash))
+            })
+            .cloned()
+    }
+
+    pub async fn set_last_channel(&self, user_id: u32, channel_id: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let mut guard = self.users.write().await;
+        let Some(user) = guard.get_mut(&user_id) else {
+            return Ok(());
+        };
+        if user.last_channel_id == Some(channel_id) {
+            return Ok(());
+        }
+
+        user.last_channel_id = Some(channel_id);
+        self.save(&guard).await
+    }
+
+    pub async fn set_comment_hash(&self, user_id: u32, hash: Option<&[u8]>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut guard = self.users.write().await;
+        let Some(user) = guard.get_mut(&user_id) else {
+            return Ok(());
+        };
+        let hash = hash.map(hex::encode);
+        if user.comment_hash == hash {
+            return Ok(());
+        }
+
+        user.comment_hash = hash;
+        self.save(&guard).await
+    }
+
+    pub async fn set_texture_hash(&self, user_id: u32, hash: Option<&[u8]>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut guard = self.users.write().await;
+        let Some(user) = guard.get_mut(&user_id) else {
+            return Ok(());
+        };
+        let hash = hash.map(hex::encode);
+        if user.texture_hash == hash {
+            return Ok(());
+        }
+
+        user.texture_hash = hash;
+        self.save(&guard).await
+    }
+
+    /// Blob store keys of every registered user's comment and texture
+    pub async fn get_blob_hashes(&self) -> HashSet<Vec<u8>> {
+        self.users
+            .read()
+            .await
+            .values()
+            .flat_map(|u| [u.comment_hash.as_deref(), u.texture_hash.as_deref()])
+            .flatten()
+            .filter_map(|hash| hex::decode(hash).ok())
+            .collect()
+    }
+}
+
+impl Default for UserRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> UserRegistry {
+        let user = RegisteredUser {
+            user_id: 1,
+            name: "Alice".to_string(),
+            certificate_hash: None,
+            groups: HashSet::new(),
+            last_channel_id: None,
+            comment_hash: Some("aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d".to_string()),
+            texture_hash: None,
+        };
+        UserRegistry {
+            path: None,
+            users: RwLock::new(HashMap::from([(1, user)])),
+        }
+    }
+
+    #[tokio::test]
+    async fn blob_hashes_follow_content_changes() {
+        let registry = registry();
+        let comment = hex::decode("aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d").unwrap();
+        assert_eq!(registry.get_blob_hashes().await, HashSet::from([comment.clone()]));
+
+        let texture = vec![7; 20];
+        registry.set_texture_hash(1, Some(&texture)).await.unwrap();
+        registry.set_comment_hash(1, None).await.unwrap();
+        registry.set_comment_hash(2, Some(&comment)).await.unwrap();
+        assert_eq!(registry.get_blob_hashes().await, HashSet::from([texture]));
+    }
+
+    #[tokio::test]
+    async fn resolve_skips_unknown_entries() {
+        let registry = registry();
+        let resolved = registry
+            .resolve(&[1, 7], &[" ALICE ".to_string(), "carol".to_string()])
+            .await;
+
+        assert_eq!(resolved, vec![(1, "Alice".to_string()), (1, "Alice".to_string())]);
+    }
+}
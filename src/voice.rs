@@ -0,0 +1,84 @@
+//! Legacy (pre-1.5) voice packets, the only format clients use with this server.
+//!
+//! The first byte holds the packet type in the upper three bits and the target in
+//! the lower five. Packets relayed to listeners carry the sender's session as a
+//! varint right after that byte.
+
+pub const TYPE_PING: u8 = 1;
+
+/// Sent to the users of the speaker's channel
+pub const TARGET_NORMAL: u8 = 0;
+/// Sent back to the speaker only
+pub const TARGET_LOOPBACK: u8 = 31;
+
+pub fn packet_type(header: u8) -> u8 {
+    header >> 5
+}
+
+pub fn target(header: u8) -> u8 {
+    header & 0x1f
+}
+
+/// Appends `value` in Mumble's variable length integer encoding
+pub fn write_varint(out: &mut Vec<u8>, value: u32) {
+    match value {
+        0..=0x7f => out.push(value as u8),
+        0x80..=0x3fff => out.extend_from_slice(&[0x80 | (value >> 8) as u8, value as u8]),
+        0x4000..=0x1f_ffff => out.extend_from_slice(&[0xc0 | (value >> 16) as u8, (value >> 8) as u8, value as u8]),
+        0x20_0000..=0x0fff_ffff => out.extend_from_slice(&[
+            0xe0 | (value >> 24) as u8,
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
+        ]),
+        _ => {
+            out.push(0xf0);
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+/// Rewrites a packet received from `session` for its listeners: the target bits are
+/// replaced by `context` and the session is inserted after the header.
+pub fn relay_packet(packet: &[u8], session: u32, context: u8) -> Vec<u8> {
+    let Some((&header, body)) = packet.split_first() else {
+        return Vec::new();
+    };
+
+    let mut relayed = Vec::with_capacity(packet.len() + 5);
+    relayed.push((header & 0xe0) | (context & 0x1f));
+    write_varint(&mut relayed, session);
+    relayed.extend_from_slice(body);
+    relayed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_varints() {
+        let encode = |value| {
+            let mut out = Vec::new();
+            write_varint(&mut out, value);
+            out
+        };
+
+        assert_eq!(encode(5), [0x05]);
+        assert_eq!(encode(0x1234), [0x92, 0x34]);
+        assert_eq!(encode(0x12_3456), [0xd2, 0x34, 0x56]);
+        assert_eq!(encode(0x0123_4567), [0xe1, 0x23, 0x45, 0x67]);
+        assert_eq!(encode(0x8000_0001), [0xf0, 0x80, 0x00, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn relays_with_sender_session() {
+        // Opus, whisper target 3, sequence 7, payload
+        let packet = [0x83, 0x07, 0xaa, 0xbb];
+        assert_eq!(packet_type(packet[0]), 4);
+        assert_eq!(target(packet[0]), 3);
+
+        assert_eq!(relay_packet(&packet, 0x1234, TARGET_NORMAL), [0x80, 0x92, 0x34, 0x07, 0xaa, 0xbb]);
+        assert!(relay_packet(&[], 1, TARGET_NORMAL).is_empty());
+    }
+}
@@ -7,3 +7,12 @@ pub trait CryptoProvider : Send + Sync {
     fn encrypt(&self, destination: &mut [u8], source: &[u8], nonce: &[u8]);
     fn decrypt(&self, destination: &mut [u8], source: &[u8], nonce: &[u8]) -> bool;
 }
+
+/// Packet accounting of one direction of an encrypted voice channel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CryptoCounters {
+    pub good: u32,
+    pub late: u32,
+    pub lost: u32,
+    pub resync: u32,
+}